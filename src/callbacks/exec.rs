@@ -20,6 +20,9 @@ pub struct RemoteExecCallback {
 }
 
 // Receive string directly instead of struct to avoid redundant parsing
+/// # Errors
+///
+/// When the task cannot be parsed or its result cannot be reported.
 pub async fn exec_command(
     utf8_str: &str,
     callback_url: String,
@@ -70,7 +73,7 @@ pub async fn exec_command(
     #[cfg(all(feature = "ureq-support", not(feature = "nyquest-support")))]
     {
         use crate::utils::create_ureq_agent;
-        let agent = create_ureq_agent(*ignore_unsafe_cert);
+        let agent = create_ureq_agent(&callback_url, *ignore_unsafe_cert);
         if let Ok(req) = agent.post(callback_url).send(&json_string) {
             if req.status().is_success() {
                 Ok(())
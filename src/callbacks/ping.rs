@@ -57,6 +57,9 @@ fn split_address(addr: &str) -> (String, u16) {
     (addr.to_string(), 80)
 }
 
+/// # Errors
+///
+/// When the task cannot be parsed or the ping type is not supported.
 pub async fn ping_target(utf8_str: &str) -> Result<PingEventCallback, String> {
     let ping_event: PingEvent =
         miniserde::json::from_str(utf8_str).map_err(|_| "Failed to parse PingEvent".to_string())?;
@@ -168,6 +171,9 @@ pub async fn ping_target(utf8_str: &str) -> Result<PingEventCallback, String> {
     }
 }
 
+/// # Errors
+///
+/// When `host_or_ip` is neither an address nor a name that resolves.
 pub async fn get_ip_from_string(host_or_ip: &str) -> Result<IpAddr, String> {
     if let Ok(ip) = IpAddr::from_str(host_or_ip) {
         return Ok(ip);
@@ -189,6 +195,13 @@ pub async fn get_ip_from_string(host_or_ip: &str) -> Result<IpAddr, String> {
     }
 }
 
+/// # Errors
+///
+/// When the ICMP socket cannot be opened or no reply arrives.
+///
+/// # Panics
+///
+/// When the socket cannot be bound to the unspecified address.
 pub fn icmp_ipv4(ip: Ipv4Addr, task_id: u64) -> Result<PingEventCallback, String> {
     let Ok(mut socket4) = IcmpSocket4::new() else {
         return Err(String::from("Failed to create Raw socket"));
@@ -274,6 +287,13 @@ pub fn icmp_ipv4(ip: Ipv4Addr, task_id: u64) -> Result<PingEventCallback, String
     })
 }
 
+/// # Errors
+///
+/// When the ICMP socket cannot be opened or no reply arrives.
+///
+/// # Panics
+///
+/// When the socket cannot be bound to the unspecified address.
 pub fn icmp_ipv6(ip: Ipv6Addr, task_id: u64) -> Result<PingEventCallback, String> {
     let Ok(mut socket6) = IcmpSocket6::new() else {
         return Err(String::from("Failed to create Raw socket"));
@@ -14,6 +14,9 @@ pub struct TerminalEvent {
     request_id: String,
 }
 
+/// # Errors
+///
+/// When the terminal event cannot be parsed.
 pub fn get_pty_ws_link(utf8_str: &str, ws_terminal_url: &str) -> Result<String, String> {
     let ping_event: TerminalEvent = miniserde::json::from_str(utf8_str)
         .map_err(|_| "Failed to parse TerminalEvent".to_string())?;
@@ -24,6 +27,9 @@ pub fn get_pty_ws_link(utf8_str: &str, ws_terminal_url: &str) -> Result<String,
     ))
 }
 
+/// # Errors
+///
+/// When the pseudo terminal or the shell cannot be started.
 pub async fn handle_pty_session<S>(ws_stream: WebSocketStream<S>, cmd: &str) -> Result<(), String>
 where
     S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
@@ -1,23 +1,22 @@
-use crate::config::IpProvider;
-
-use crate::get_info::cpu::{arch, cpu_info_without_usage, realtime_cpu};
-use crate::get_info::ip::ip;
-use crate::get_info::load::realtime_load;
-use crate::get_info::mem::{mem_info_without_usage, realtime_disk, realtime_mem, realtime_swap};
-use crate::get_info::network::{realtime_connections, realtime_network};
+use crate::config::UserConfig;
+
+use crate::get_info::cgroup::Cgroup;
+use crate::get_info::cpu::{arch, cpu_info_without_usage};
+use crate::get_info::cpu_inventory::cpu_inventory;
+use crate::get_info::hardware::hardware_inventory;
+use crate::get_info::ip::{IPInfo, IpDetector, ip};
+use crate::get_info::mem::{DiskFilter, mem_info_without_usage};
 use crate::get_info::os::os;
-use crate::get_info::{realtime_process, realtime_uptime};
+use crate::get_info::{Collector, RealTimeCollectors};
 use log::{debug, error, info};
 use miniserde::{Deserialize, Serialize};
-use sysinfo::{Disks, Networks};
-use tokio::sync::mpsc::Receiver;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BasicInfo {
     pub arch: String,
     pub cpu_cores: u64,
     pub cpu_name: String,
-    pub gpu_name: String, // Not supported yet
+    pub gpu_name: String,
 
     pub disk_total: u64,
     pub swap_total: u64,
@@ -30,13 +29,116 @@ pub struct BasicInfo {
     pub version: String,
     pub kernel_version: String,
     pub virtualization: String,
+
+    pub cpu_inventory: CpuInventory,
+    pub hardware: HardwareInventory,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockDevice {
+    pub name: String,
+    pub model: String,
+    pub serial: String,
+    /// Bytes
+    pub size: u64,
+    /// Spinning disk rather than SSD/NVMe
+    pub rotational: bool,
+    pub removable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub mac: String,
+    /// Kernel driver, empty for virtual interfaces
+    pub driver: String,
+    /// Link speed in Mbit/s
+    pub speed: Option<u64>,
+    /// Addresses with prefix length, e.g. `192.0.2.1/24`
+    pub ipv4: Vec<String>,
+    pub ipv6: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HardwareInventory {
+    pub block_devices: Vec<BlockDevice>,
+    pub network_interfaces: Vec<NetworkInterface>,
+}
+
+impl HardwareInventory {
+    pub fn scale(&mut self, fake: f64) {
+        for device in &mut self.block_devices {
+            device.size = (device.size as f64 * fake) as u64;
+        }
+    }
+}
+
+/// CPU topology and features; frequencies are in MHz and cache sizes in bytes
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CpuInventory {
+    pub logical_cores: u64,
+    pub physical_cores: u64,
+    pub sockets: u64,
+    pub base_frequency: Option<u64>,
+    pub max_frequency: Option<u64>,
+    /// Per core for L1 and L2, usually shared per socket for L3
+    pub cache_l1d: Option<u64>,
+    pub cache_l1i: Option<u64>,
+    pub cache_l2: Option<u64>,
+    pub cache_l3: Option<u64>,
+    /// `vmx` or `svm` when hardware virtualization is exposed to us
+    pub virtualization: Option<String>,
+    pub aes: bool,
+    pub avx2: bool,
+    pub avx512: bool,
+    pub numa_nodes: u64,
+}
+
+impl CpuInventory {
+    /// Scale every topology count together so the reported topology stays
+    /// consistent; cache sizes and frequencies are per core and left alone
+    pub fn scale(&mut self, fake: f64) {
+        for count in [
+            &mut self.logical_cores,
+            &mut self.physical_cores,
+            &mut self.sockets,
+            &mut self.numa_nodes,
+        ] {
+            *count = (*count as f64 * fake) as u64;
+        }
+    }
 }
 
 impl BasicInfo {
-    pub async fn build(sysinfo_sys: &sysinfo::System, fake: f64, ip_provider: &IpProvider) -> Self {
-        let cpu = cpu_info_without_usage(sysinfo_sys);
-        let mem_disk = mem_info_without_usage(sysinfo_sys);
-        let (ip, os) = tokio::join!(ip(ip_provider), os());
+    /// `gpu_name` comes from `GpuCollector::name`, or `gpu_name()` when GPU
+    /// metrics are disabled, so that nvidia-smi never runs here
+    pub async fn build(
+        sysinfo_sys: &sysinfo::System,
+        config: &UserConfig,
+        gpu_name: String,
+    ) -> Self {
+        let fake = config.fake;
+        let mut cpu = cpu_info_without_usage(sysinfo_sys);
+        let mut mem_disk = mem_info_without_usage(sysinfo_sys, &DiskFilter::from_config(config));
+        let ip_detector = IpDetector::from_config(config);
+        let (ip, os) = tokio::join!(ip(&ip_detector), os(&config.os_format));
+
+        if config.cgroup_metrics
+            && let Some(cgroup) = Cgroup::detect()
+        {
+            if let Some(cpu_limit) = cgroup.cpu_limit() {
+                cpu.cores = cpu.cores.min(cpu_limit.ceil() as u16).max(1);
+            }
+            if let Some(memory_limit) = cgroup.memory_limit() {
+                mem_disk.mem = mem_disk.mem.min(memory_limit);
+            }
+        }
+
+        let mut cpu_inventory = cpu_inventory(sysinfo_sys);
+        cpu_inventory.scale(fake);
+
+        let mut hardware = hardware_inventory();
+        hardware.scale(fake);
 
         let fake_cpu_cores = (f64::from(cpu.cores) * fake) as u64;
         let fake_disk_total = (mem_disk.disk as f64 * fake) as u64;
@@ -47,7 +149,7 @@ impl BasicInfo {
             arch: arch(),
             cpu_cores: fake_cpu_cores,
             cpu_name: cpu.name,
-            gpu_name: String::new(),
+            gpu_name,
             disk_total: fake_disk_total,
             swap_total: fake_swap_total,
             mem_total: fake_mem_total,
@@ -57,6 +159,8 @@ impl BasicInfo {
             version: format!("komari-monitor-rs {}", env!("CARGO_PKG_VERSION")),
             kernel_version: os.version,
             virtualization: os.virtualization,
+            cpu_inventory,
+            hardware,
         };
 
         debug!("Basic Info successfully retrieved: {basic_info:?}");
@@ -64,12 +168,61 @@ impl BasicInfo {
         basic_info
     }
 
+    /// Take the addresses from a re-detection, returning whether either changed.
+    /// A family that could not be detected keeps its previous address, since a
+    /// failed lookup says nothing about the address going away.
+    pub fn update_ip(&mut self, ip: &IPInfo) -> bool {
+        let mut changed = false;
+        for (family, current, detected) in [
+            ("IPv4", &mut self.ipv4, ip.ipv4.map(|ip| ip.to_string())),
+            ("IPv6", &mut self.ipv6, ip.ipv6.map(|ip| ip.to_string())),
+        ] {
+            if let Some(detected) = detected
+                && current.as_ref() != Some(&detected)
+            {
+                info!(
+                    "Public {family} address changed: {} -> {detected}",
+                    current.as_deref().unwrap_or("none")
+                );
+                *current = Some(detected);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Take a new hardware inventory (already scaled by `fake`), returning whether it changed
+    pub fn update_hardware(&mut self, hardware: HardwareInventory) -> bool {
+        if self.hardware == hardware {
+            return false;
+        }
+        let names = |inventory: &HardwareInventory| -> Vec<String> {
+            inventory
+                .block_devices
+                .iter()
+                .map(|device| format!("disk {}", device.name))
+                .chain(
+                    inventory
+                        .network_interfaces
+                        .iter()
+                        .map(|interface| format!("interface {}", interface.name)),
+                )
+                .collect()
+        };
+        let (old, new) = (names(&self.hardware), names(&hardware));
+        let added: Vec<&String> = new.iter().filter(|name| !old.contains(name)).collect();
+        let removed: Vec<&String> = old.iter().filter(|name| !new.contains(name)).collect();
+        info!("Hardware inventory changed: added {added:?}, removed {removed:?}");
+        self.hardware = hardware;
+        true
+    }
+
     pub fn push(&self, basic_info_url: String, ignore_unsafe_cert: bool) {
         let json_string = miniserde::json::to_string(self);
         #[cfg(feature = "ureq-support")]
         {
             use crate::utils::create_ureq_agent;
-            let agent = create_ureq_agent(ignore_unsafe_cert);
+            let agent = create_ureq_agent(&basic_info_url, ignore_unsafe_cert);
             let resp = agent
                 .post(basic_info_url)
                 .header("User-Agent", "curl/11.45.14-rs")
@@ -120,14 +273,70 @@ impl BasicInfo {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Time shares are percentages of all CPU time since the previous sample
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Cpu {
     pub usage: f64,
+    pub user: f64,
+    pub system: f64,
+    pub nice: f64,
+    pub iowait: f64,
+    pub irq: f64,
+    pub softirq: f64,
+    pub steal: f64,
+    pub per_core: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GpuDetail {
+    pub name: String,
+    pub utilization: f64,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub temperature: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Gpu {
+    pub count: u64,
+    pub average_usage: f64,
+    pub detailed_info: Vec<GpuDetail>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TemperatureSensor {
+    pub chip: String,
+    pub label: String,
+    /// Degrees Celsius
+    pub temperature: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FanSensor {
+    pub chip: String,
+    pub label: String,
+    pub rpm: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sensors {
+    /// Hottest CPU package/core temperature
+    pub cpu_temperature: Option<f64>,
+    pub temperatures: Vec<TemperatureSensor>,
+    pub fans: Vec<FanSensor>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Ram {
     pub used: u64,
+    pub available: u64,
+    pub cached: u64,
+    pub buffers: u64,
+    pub shared: u64,
+    pub dirty: u64,
+    pub writeback: u64,
+    /// `None` when ZFS is not loaded
+    pub zfs_arc: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -135,9 +344,34 @@ pub struct Swap {
     pub used: u64,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DiskIo {
+    pub name: String,
+    /// Bytes per second
+    pub read_speed: u64,
+    pub write_speed: u64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Average time per completed request, in milliseconds
+    pub await_ms: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiskMount {
+    pub mount_point: String,
+    pub fs_type: String,
+    pub used: u64,
+    pub total: u64,
+    pub inodes_used: u64,
+    pub inodes_total: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Disk {
     pub used: u64,
+    pub io: DiskIo,
+    pub devices: Vec<DiskIo>,
+    pub mounts: Vec<DiskMount>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -147,6 +381,51 @@ pub struct Load {
     pub load15: f64,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub user: String,
+    /// Truncated to 256 bytes
+    pub cmdline: String,
+    /// Percent of one core since the previous snapshot
+    pub cpu: f64,
+    /// Resident set size in bytes
+    pub rss: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TopProcesses {
+    pub by_cpu: Vec<ProcessInfo>,
+    pub by_memory: Vec<ProcessInfo>,
+}
+
+/// One `some` or `full` line of a PSI file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PressureStall {
+    /// Percent of wall time stalled over the last 10/60/300 seconds
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    /// Total stall time in microseconds
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PressureResource {
+    pub some: PressureStall,
+    /// Not reported for CPU before Linux 5.13
+    pub full: Option<PressureStall>,
+}
+
+/// `None` resources mean PSI is unavailable (kernel < 4.20 or `psi=0`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Pressure {
+    pub cpu: Option<PressureResource>,
+    pub memory: Option<PressureResource>,
+    pub io: Option<PressureResource>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Network {
     pub up: u64,
@@ -158,10 +437,113 @@ pub struct Network {
     pub total_down: u64,
 }
 
+/// Counters of one network interface; rates are bytes per second
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InterfaceStats {
+    pub name: String,
+    /// Kernel operstate: up, down, dormant, unknown...
+    pub state: String,
+    pub mtu: u64,
+    /// Link speed in Mbit/s, unknown for virtual interfaces and links that are down
+    pub speed: Option<u64>,
+    pub rx_rate: u64,
+    pub tx_rate: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    /// Only available on Linux
+    pub rx_dropped: Option<u64>,
+    pub tx_dropped: Option<u64>,
+}
+
+/// Number of TCP sockets in each state
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TcpStates {
+    pub established: u64,
+    pub syn_sent: u64,
+    pub syn_recv: u64,
+    pub fin_wait1: u64,
+    pub fin_wait2: u64,
+    pub time_wait: u64,
+    pub close: u64,
+    pub close_wait: u64,
+    pub last_ack: u64,
+    pub listen: u64,
+    pub closing: u64,
+}
+
+impl TcpStates {
+    /// Count one socket in state `state`, numbered as in the Linux kernel
+    pub fn add(&mut self, state: u8) {
+        let counter = match state {
+            1 => &mut self.established,
+            2 => &mut self.syn_sent,
+            // 12 is TCP_NEW_SYN_RECV, a pending request socket
+            3 | 12 => &mut self.syn_recv,
+            4 => &mut self.fin_wait1,
+            5 => &mut self.fin_wait2,
+            6 => &mut self.time_wait,
+            7 => &mut self.close,
+            8 => &mut self.close_wait,
+            9 => &mut self.last_ack,
+            10 => &mut self.listen,
+            11 => &mut self.closing,
+            _ => return,
+        };
+        *counter += 1;
+    }
+
+    pub fn scale(&mut self, fake: f64) {
+        for counter in [
+            &mut self.established,
+            &mut self.syn_sent,
+            &mut self.syn_recv,
+            &mut self.fin_wait1,
+            &mut self.fin_wait2,
+            &mut self.time_wait,
+            &mut self.close,
+            &mut self.close_wait,
+            &mut self.last_ack,
+            &mut self.listen,
+            &mut self.closing,
+        ] {
+            *counter = (*counter as f64 * fake) as u64;
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Connections {
+    /// Established TCP connections
     pub tcp: u64,
     pub udp: u64,
+    pub tcp_states_v4: TcpStates,
+    pub tcp_states_v6: TcpStates,
+}
+
+/// A value from a custom metric command or textfile
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustomMetric {
+    /// Metric name, including Prometheus labels when present
+    pub name: String,
+    pub value: f64,
+    /// The command or `*.prom` file it came from
+    pub source: String,
+}
+
+/// A listening TCP or bound UDP socket
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListeningSocket {
+    /// `tcp` or `udp`
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+    /// Unknown for other users' sockets when not running as root
+    pub pid: Option<u32>,
+    pub process: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -172,59 +554,135 @@ pub struct RealTimeInfo {
     pub disk: Disk,
     pub load: Load,
     pub network: Network,
+    pub interfaces: Vec<InterfaceStats>,
     pub connections: Connections,
+    pub gpu: Option<Gpu>,
+    pub sensors: Sensors,
+    pub pressure: Pressure,
+    /// PSI of the agent's own cgroup (cgroup v2 only)
+    pub cgroup_pressure: Option<Pressure>,
     pub uptime: u64,
     pub process: u64,
+    /// Only present on samples where a new snapshot was taken
+    pub top_processes: Option<TopProcesses>,
+    /// Only present on samples where a new inventory was taken
+    pub listening: Option<Vec<ListeningSocket>>,
+    pub custom_metrics: Vec<CustomMetric>,
     pub message: String,
+    /// Sample time (RFC 3339), only set with `offline_buffer` so that replayed
+    /// samples keep the time they were taken
+    pub updated_at: Option<String>,
 }
 
 impl RealTimeInfo {
-    pub fn build(
-        sysinfo_sys: &sysinfo::System,
-        network: &Networks,
-        network_saver_rx: Option<&mut Receiver<(u64, u64)>>,
-        disk: &Disks,
-        fake: f64,
-    ) -> Self {
-        let cpu = realtime_cpu(sysinfo_sys);
+    pub fn build(collectors: &mut RealTimeCollectors, fake: f64) -> Self {
+        let mut cpu = collectors.cpu.collect();
 
-        let ram = realtime_mem(sysinfo_sys);
-        let fake_ram_used = (ram.used as f64 * fake) as u64;
+        let (mut ram, swap) = collectors.mem.collect();
 
-        let swap = realtime_swap(sysinfo_sys);
+        if let Some(cgroup) = collectors.cgroup.as_mut() {
+            let usage = cgroup.collect();
+            if let Some(cpu_usage) = usage.cpu_usage {
+                cpu.usage = cpu_usage;
+            }
+            if let Some(memory_used) = usage.memory_used {
+                ram.used = memory_used;
+                if let Some(memory_limit) = usage.memory_limit {
+                    ram.available = memory_limit.saturating_sub(memory_used);
+                }
+            }
+        }
+        for value in [
+            &mut ram.used,
+            &mut ram.available,
+            &mut ram.cached,
+            &mut ram.buffers,
+            &mut ram.shared,
+            &mut ram.dirty,
+            &mut ram.writeback,
+        ]
+        .into_iter()
+        .chain(ram.zfs_arc.as_mut())
+        {
+            *value = (*value as f64 * fake) as u64;
+        }
         let fake_swap_used = (swap.used as f64 * fake) as u64;
 
-        let disk_info = realtime_disk(disk);
+        let mut disk_info = collectors.disk.collect();
         let fake_disk_used = (disk_info.used as f64 * fake) as u64;
+        for io in std::iter::once(&mut disk_info.io).chain(disk_info.devices.iter_mut()) {
+            io.read_speed = (io.read_speed as f64 * fake) as u64;
+            io.write_speed = (io.write_speed as f64 * fake) as u64;
+            io.read_iops *= fake;
+            io.write_iops *= fake;
+        }
+        for mount in &mut disk_info.mounts {
+            mount.used = (mount.used as f64 * fake) as u64;
+            mount.total = (mount.total as f64 * fake) as u64;
+        }
 
-        let load = realtime_load();
+        let load = collectors.load.collect();
         let fake_load1 = load.load1 * fake;
         let fake_load5 = load.load5 * fake;
         let fake_load15 = load.load15 * fake;
 
-        let network_info = realtime_network(network, network_saver_rx);
+        let (network_info, mut interfaces) = collectors.network.collect();
+        for interface in &mut interfaces {
+            for value in [
+                &mut interface.rx_rate,
+                &mut interface.tx_rate,
+                &mut interface.rx_bytes,
+                &mut interface.tx_bytes,
+                &mut interface.rx_packets,
+                &mut interface.tx_packets,
+            ] {
+                *value = (*value as f64 * fake) as u64;
+            }
+        }
         let fake_network_up = (network_info.up as f64 * fake) as u64;
         let fake_network_down = (network_info.down as f64 * fake) as u64;
         let fake_network_total_up = (network_info.total_up as f64 * fake) as u64;
         let fake_network_total_down = (network_info.total_down as f64 * fake) as u64;
 
-        let connections = realtime_connections();
+        let mut connections = collectors.connections.collect();
         let fake_connections_tcp = (connections.tcp as f64 * fake) as u64;
         let fake_connections_udp = (connections.udp as f64 * fake) as u64;
+        connections.tcp_states_v4.scale(fake);
+        connections.tcp_states_v6.scale(fake);
+
+        let gpu = collectors.gpu.as_mut().and_then(Collector::collect);
 
-        let process = realtime_process();
+        let sensors = collectors.sensors.collect();
+
+        let (pressure, cgroup_pressure) = collectors.pressure.collect();
+
+        let process = collectors.process.collect();
         let fake_process = (process as f64 * fake) as u64;
 
+        let top_processes = collectors
+            .top_processes
+            .as_mut()
+            .and_then(Collector::collect);
+
+        let listening = collectors.listening.as_mut().and_then(Collector::collect);
+
+        let custom_metrics = collectors
+            .custom_metrics
+            .as_mut()
+            .map(Collector::collect)
+            .unwrap_or_default();
+
         let realtime_info = Self {
             cpu,
-            ram: Ram {
-                used: fake_ram_used,
-            },
+            ram,
             swap: Swap {
                 used: fake_swap_used,
             },
             disk: Disk {
                 used: fake_disk_used,
+                io: disk_info.io,
+                devices: disk_info.devices,
+                mounts: disk_info.mounts,
             },
             load: Load {
                 load1: fake_load1,
@@ -237,13 +695,28 @@ impl RealTimeInfo {
                 total_up: fake_network_total_up,
                 total_down: fake_network_total_down,
             },
+            interfaces,
             connections: Connections {
                 tcp: fake_connections_tcp,
                 udp: fake_connections_udp,
+                tcp_states_v4: connections.tcp_states_v4,
+                tcp_states_v6: connections.tcp_states_v6,
             },
-            uptime: realtime_uptime(),
+            gpu,
+            sensors,
+            pressure,
+            cgroup_pressure,
+            uptime: collectors.uptime.collect(),
             process: fake_process,
-            message: String::new(),
+            top_processes,
+            listening,
+            custom_metrics,
+            message: collectors
+                .watchdog
+                .as_mut()
+                .map(Collector::collect)
+                .unwrap_or_default(),
+            updated_at: None,
         };
 
         debug!("Real-Time Info successfully retrieved: {realtime_info:?}");
@@ -251,3 +724,71 @@ impl RealTimeInfo {
         realtime_info
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CpuInventory, TcpStates};
+
+    #[test]
+    fn tcp_states_add_uses_kernel_numbering() {
+        let mut states = TcpStates::default();
+        for state in [1, 1, 2, 3, 12, 4, 5, 6, 7, 8, 9, 10, 10, 11, 0, 13, 255] {
+            states.add(state);
+        }
+        assert_eq!(states.established, 2);
+        assert_eq!(states.syn_sent, 1);
+        // TCP_NEW_SYN_RECV request sockets count as SYN_RECV
+        assert_eq!(states.syn_recv, 2);
+        assert_eq!(states.fin_wait1, 1);
+        assert_eq!(states.fin_wait2, 1);
+        assert_eq!(states.time_wait, 1);
+        assert_eq!(states.close, 1);
+        assert_eq!(states.close_wait, 1);
+        assert_eq!(states.last_ack, 1);
+        assert_eq!(states.listen, 2);
+        assert_eq!(states.closing, 1);
+    }
+
+    #[test]
+    fn tcp_states_scale_every_counter() {
+        let mut states = TcpStates::default();
+        for state in 1..=11 {
+            states.add(state);
+            states.add(state);
+        }
+        states.scale(1.5);
+        for counter in [
+            states.established,
+            states.syn_sent,
+            states.syn_recv,
+            states.fin_wait1,
+            states.fin_wait2,
+            states.time_wait,
+            states.close,
+            states.close_wait,
+            states.last_ack,
+            states.listen,
+            states.closing,
+        ] {
+            assert_eq!(counter, 3);
+        }
+    }
+
+    #[test]
+    fn cpu_inventory_scale_keeps_the_topology_consistent() {
+        let mut inventory = CpuInventory {
+            logical_cores: 32,
+            physical_cores: 16,
+            sockets: 2,
+            numa_nodes: 2,
+            cache_l2: Some(1024 * 1024),
+            ..CpuInventory::default()
+        };
+        inventory.scale(2.0);
+        assert_eq!(inventory.logical_cores, 64);
+        assert_eq!(inventory.physical_cores, 32);
+        assert_eq!(inventory.sockets, 4);
+        assert_eq!(inventory.numa_nodes, 4);
+        assert_eq!(inventory.cache_l2, Some(1024 * 1024));
+    }
+}
@@ -1,12 +1,29 @@
+use crate::config::UserConfig;
+use crate::data_struct::PressureResource;
+use crate::get_info::Collector;
+use crate::get_info::cgroup::Cgroup;
 use crate::get_info::cpu::cpu_info_without_usage;
+use crate::get_info::cpu_inventory::cpu_inventory;
+use crate::get_info::custom::{read_textfile_dir, run_command};
+use crate::get_info::disk_io::diskstats_device_name;
+use crate::get_info::gpu::GpuCollector;
+use crate::get_info::hardware::hardware_inventory;
 use crate::get_info::load::realtime_load;
-use crate::get_info::mem::{filter_disks, mem_info_without_usage, realtime_mem, realtime_swap};
-use crate::get_info::network::realtime_connections;
+use crate::get_info::mem::{
+    DiskFilter, filter_disks, mem_info_without_usage, mount_inodes, realtime_mem, realtime_swap,
+};
+use crate::get_info::network::listening::{ListeningCollector, describe};
+use crate::get_info::network::{NetworkCollector, is_filtered_interface, realtime_connections};
+use crate::get_info::pressure::PressureCollector;
+use crate::get_info::processes::TopProcessCollector;
+use crate::get_info::sensors::{SensorCollector, SensorKind};
+use crate::watchdog::Watchdog;
 use log::info;
-use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
 use sysinfo::{Disks, Networks};
 
-pub async fn dry_run() {
+pub async fn dry_run(config: &UserConfig) {
     info!("The following is the equipment that will be put into operation and monitored:");
     let mut sysinfo_sys = sysinfo::System::new();
     let networks = Networks::new_with_refreshed_list();
@@ -15,21 +32,210 @@ pub async fn dry_run() {
 
     let cpu = cpu_info_without_usage(&sysinfo_sys);
     info!("CPU: {}, Cores: {}", cpu.name, cpu.cores);
+    let inventory = cpu_inventory(&sysinfo_sys);
+    let mhz = |frequency: Option<u64>| frequency.map_or("N/A".to_string(), |f| format!("{f} MHz"));
+    let kib = |size: Option<u64>| size.map_or("N/A".to_string(), |s| format!("{} KiB", s / 1024));
+    info!(
+        "CPU Topology: {} sockets | {} physical / {} logical cores | {} NUMA nodes | Base {} / Max {}",
+        inventory.sockets,
+        inventory.physical_cores,
+        inventory.logical_cores,
+        inventory.numa_nodes,
+        mhz(inventory.base_frequency),
+        mhz(inventory.max_frequency)
+    );
+    info!(
+        "CPU Cache: L1d {} | L1i {} | L2 {} | L3 {}",
+        kib(inventory.cache_l1d),
+        kib(inventory.cache_l1i),
+        kib(inventory.cache_l2),
+        kib(inventory.cache_l3)
+    );
+    info!(
+        "CPU Features: virtualization {} | AES {} | AVX2 {} | AVX-512 {}",
+        inventory.virtualization.as_deref().unwrap_or("none"),
+        inventory.aes,
+        inventory.avx2,
+        inventory.avx512
+    );
+
+    let hardware = hardware_inventory();
+    for device in &hardware.block_devices {
+        info!(
+            "Block Device: {} | {} | Serial {} | {} GB | {}{}",
+            device.name,
+            if device.model.is_empty() {
+                "Unknown model"
+            } else {
+                &device.model
+            },
+            if device.serial.is_empty() {
+                "N/A"
+            } else {
+                &device.serial
+            },
+            device.size / 1000 / 1000 / 1000,
+            if device.rotational { "HDD" } else { "SSD" },
+            if device.removable { " | removable" } else { "" }
+        );
+    }
+    for interface in &hardware.network_interfaces {
+        info!(
+            "Network Interface: {} | MAC {} | Driver {} | Speed {} | IPv4 {:?} | IPv6 {:?}",
+            interface.name,
+            interface.mac,
+            if interface.driver.is_empty() {
+                "N/A"
+            } else {
+                &interface.driver
+            },
+            interface
+                .speed
+                .map_or("N/A".to_string(), |speed| format!("{speed} Mbit/s")),
+            interface.ipv4,
+            interface.ipv6
+        );
+    }
+
+    if let Some(gpu) = GpuCollector::from_config(config).and_then(|mut gpu| gpu.collect()) {
+        for detail in gpu.detailed_info {
+            info!(
+                "GPU: {} | {:.0}% | {} MB / {} MB | {:.1} °C",
+                detail.name,
+                detail.utilization,
+                detail.memory_used / 1000 / 1000,
+                detail.memory_total / 1000 / 1000,
+                detail.temperature
+            );
+        }
+    } else {
+        info!("GPU: None");
+    }
 
-    let mem_with_out_usage = mem_info_without_usage(&sysinfo_sys);
-    let mem = realtime_mem(&sysinfo_sys);
+    let disk_filter = DiskFilter::from_config(config);
+    let mem_with_out_usage = mem_info_without_usage(&sysinfo_sys, &disk_filter);
+    let mem = realtime_mem(&sysinfo_sys, &config.mem_used_mode);
     let swap = realtime_swap(&sysinfo_sys);
     info!(
         "Memory: {} MB / {} MB",
         mem.used / 1000 / 1000,
         mem_with_out_usage.mem / 1000 / 1000
     );
+    info!(
+        "Memory Breakdown: available {} MB | cached {} MB | buffers {} MB | shared {} MB | dirty {} MB",
+        mem.available / 1000 / 1000,
+        mem.cached / 1000 / 1000,
+        mem.buffers / 1000 / 1000,
+        mem.shared / 1000 / 1000,
+        mem.dirty / 1000 / 1000
+    );
+    if let Some(zfs_arc) = mem.zfs_arc {
+        info!("ZFS ARC: {} MB", zfs_arc / 1000 / 1000);
+    }
     info!(
         "Swap: {} MB / {} MB",
         swap.used / 1000 / 1000,
         mem_with_out_usage.swap / 1000 / 1000
     );
 
+    if config.cgroup_metrics {
+        if let Some(cgroup) = Cgroup::detect() {
+            info!(
+                "Cgroup {:?}: CPU limit {} | Memory limit {} | Memory used {} MB",
+                cgroup.version,
+                cgroup
+                    .cpu_limit()
+                    .map_or("None".to_string(), |cores| format!("{cores:.2} cores")),
+                cgroup
+                    .memory_limit()
+                    .map_or("None".to_string(), |limit| format!(
+                        "{} MB",
+                        limit / 1000 / 1000
+                    )),
+                cgroup.memory_used().unwrap_or(0) / 1000 / 1000
+            );
+        } else {
+            info!("Cgroup: None, falling back to host metrics");
+        }
+    }
+
+    let (pressure, cgroup_pressure) = PressureCollector::new().collect();
+    for (scope, pressure) in std::iter::once(("Pressure", Some(pressure)))
+        .chain(std::iter::once(("Cgroup Pressure", cgroup_pressure)))
+        .filter_map(|(scope, pressure)| Some((scope, pressure?)))
+    {
+        let format = |resource: Option<&PressureResource>| {
+            resource.map_or("N/A".to_string(), |resource| {
+                format!(
+                    "some {:.2}/{:.2}/{:.2}",
+                    resource.some.avg10, resource.some.avg60, resource.some.avg300
+                )
+            })
+        };
+        info!(
+            "{scope}: CPU {} | Memory {} | IO {}",
+            format(pressure.cpu.as_ref()),
+            format(pressure.memory.as_ref()),
+            format(pressure.io.as_ref())
+        );
+    }
+
+    if config.top_processes > 0 {
+        let mut top_processes =
+            TopProcessCollector::new(config.top_processes as usize, Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(500));
+        if let Some(top) = top_processes.collect() {
+            for process in top.by_cpu {
+                info!(
+                    "Top CPU: [{}] {} ({}) {:.1}% | {}",
+                    process.pid, process.name, process.user, process.cpu, process.cmdline
+                );
+            }
+            for process in top.by_memory {
+                info!(
+                    "Top Memory: [{}] {} ({}) {} MB | {}",
+                    process.pid,
+                    process.name,
+                    process.user,
+                    process.rss / 1000 / 1000,
+                    process.cmdline
+                );
+            }
+        }
+    }
+
+    // Never run restart hooks from a dry run
+    if let Some(watchdog) = Watchdog::from_config(config) {
+        let missing = watchdog.with_restart_command(None).check();
+        if missing.is_empty() {
+            info!("Watchdog: all watched processes and units are running");
+        } else {
+            info!("Watchdog: missing {}", missing.join(", "));
+        }
+    }
+
+    for command in &config.custom_metric_commands {
+        match run_command(&command.command, Duration::from_secs(command.timeout)) {
+            Ok(metrics) => {
+                for metric in metrics {
+                    info!(
+                        "Custom Metric: {} = {} ({})",
+                        metric.name, metric.value, metric.source
+                    );
+                }
+            }
+            Err(e) => info!("Custom Metric: `{}` {e}", command.command),
+        }
+    }
+    if let Some(dir) = &config.custom_metrics_textfile_dir {
+        for metric in read_textfile_dir(Path::new(dir)) {
+            info!(
+                "Custom Metric: {} = {} ({})",
+                metric.name, metric.value, metric.source
+            );
+        }
+    }
+
     let load = realtime_load();
     info!(
         "Load: {:.2} / {:.2} / {:.2}",
@@ -38,34 +244,50 @@ pub async fn dry_run() {
 
     info!("");
 
+    info!("Sensors will be monitored:");
+    let mut sensor_collector = SensorCollector::new();
+    let sensors = sensor_collector.collect();
+    for source in sensor_collector.sources() {
+        let kind = match source.kind {
+            SensorKind::Temperature if source.is_cpu => "CPU Temperature",
+            SensorKind::Temperature => "Temperature",
+            SensorKind::Fan => "Fan",
+        };
+        info!(
+            "{} | {} | {} | {}",
+            source.chip,
+            source.label,
+            kind,
+            source.input.display()
+        );
+    }
+    if let Some(cpu_temperature) = sensors.cpu_temperature {
+        info!("Hottest CPU Temperature: {cpu_temperature:.1} °C");
+    }
+
+    info!("");
+
     info!("Hard drives will be monitored:");
-    let disks = filter_disks(&disks);
+    let disks = filter_disks(&disks, &disk_filter);
     for disk in disks {
+        let (inodes_used, inodes_total) = mount_inodes(disk.mount_point());
         info!(
-            "{} | {} | {} | {} GB / {} GB",
+            "{} | {} | {} | {} GB / {} GB | Inodes: {} / {} | I/O: {}",
             disk.name().to_string_lossy(),
             disk.file_system().to_string_lossy(),
             disk.mount_point().to_string_lossy(),
             disk.available_space() / 1000 / 1000 / 1000,
-            disk.total_space() / 1000 / 1000 / 1000
+            disk.total_space() / 1000 / 1000 / 1000,
+            inodes_used,
+            inodes_total,
+            diskstats_device_name(Path::new(disk.name()))
         );
     }
 
     info!("");
     info!("Network interfaces will be monitored:");
-    let filter_keywords: HashSet<&str> = [
-        "br", "cni", "docker", "podman", "flannel", "lo", "veth", "virbr", "vmbr", "tap", "tun",
-        "fwln", "fwpr",
-    ]
-    .iter()
-    .cloned()
-    .collect();
     for (name, data) in networks.iter() {
-        let should_filter = filter_keywords
-            .iter()
-            .any(|&keyword| name.contains(keyword));
-
-        if should_filter || data.mac_address().0 == [0, 0, 0, 0, 0, 0] {
+        if is_filtered_interface(name, data) {
             continue;
         } else {
             info!(
@@ -77,8 +299,47 @@ pub async fn dry_run() {
             )
         }
     }
+    let mut network_collector = NetworkCollector::new(None, config.network_interfaces.clone());
+    for interface in network_collector.collect().1 {
+        info!(
+            "Interface: {} | {} | MTU {} | Speed {} | RX {} MB / TX {} MB | Errors {} / {} | Dropped {} / {}",
+            interface.name,
+            interface.state,
+            interface.mtu,
+            interface
+                .speed
+                .map_or("N/A".to_string(), |speed| format!("{speed} Mbit/s")),
+            interface.rx_bytes / 1000 / 1000,
+            interface.tx_bytes / 1000 / 1000,
+            interface.rx_errors,
+            interface.tx_errors,
+            interface.rx_dropped.unwrap_or(0),
+            interface.tx_dropped.unwrap_or(0)
+        );
+    }
     let connections = realtime_connections();
     info!("CONNS: TCP: {} | UDP: {}", connections.tcp, connections.udp);
+    for (family, states) in [
+        ("IPv4", &connections.tcp_states_v4),
+        ("IPv6", &connections.tcp_states_v6),
+    ] {
+        info!(
+            "TCP {family}: ESTABLISHED {} | LISTEN {} | SYN_RECV {} | TIME_WAIT {} | CLOSE_WAIT {} | FIN_WAIT {}",
+            states.established,
+            states.listen,
+            states.syn_recv,
+            states.time_wait,
+            states.close_wait,
+            states.fin_wait1 + states.fin_wait2
+        );
+    }
+
+    if config.listening_interval > 0 {
+        let mut listening = ListeningCollector::new(Duration::ZERO);
+        for socket in listening.collect().unwrap_or_default() {
+            info!("Listening: {}", describe(&socket));
+        }
+    }
 
     info!("===== DIVIDING LINE =====")
 }
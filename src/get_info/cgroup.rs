@@ -0,0 +1,371 @@
+use crate::get_info::Collector;
+use log::trace;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// cgroup v1 reports "no limit" as a page-aligned `i64::MAX`
+const V1_UNLIMITED: u64 = 1 << 62;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgroupVersion {
+    V1,
+    V2,
+}
+
+/// A controller's directory for this process and the mount it lives in.
+/// Limits set on any directory between the two apply.
+#[derive(Debug, Clone)]
+struct ControllerDir {
+    mount: PathBuf,
+    dir: PathBuf,
+}
+
+impl ControllerDir {
+    fn resolve(mount: PathBuf, cgroup_path: &str) -> Self {
+        // Without a cgroup namespace the path is the host's view and does not
+        // exist inside the container; the mount itself is then our cgroup
+        let dir = mount.join(cgroup_path.trim_start_matches('/'));
+        let dir = if dir.is_dir() { dir } else { mount.clone() };
+        Self { mount, dir }
+    }
+
+    fn read(&self, file: &str) -> Option<String> {
+        read_trimmed(&self.dir, file)
+    }
+
+    /// Smallest limit from this cgroup up to the mount, as read from each
+    /// directory by `limit`
+    fn min_over_ancestors(&self, limit: impl Fn(&Path) -> Option<f64>) -> Option<f64> {
+        self.dir
+            .ancestors()
+            .take_while(|dir| dir.starts_with(&self.mount))
+            .filter_map(limit)
+            .reduce(f64::min)
+    }
+}
+
+fn read_trimmed(dir: &Path, file: &str) -> Option<String> {
+    Some(fs::read_to_string(dir.join(file)).ok()?.trim().to_string())
+}
+
+/// The cgroup this process belongs to
+#[derive(Debug, Clone)]
+pub struct Cgroup {
+    pub version: CgroupVersion,
+    cpu: Option<ControllerDir>,
+    cpuacct: Option<ControllerDir>,
+    cpuset: Option<ControllerDir>,
+    memory: Option<ControllerDir>,
+}
+
+/// Parse /proc/self/cgroup into (controllers, path) pairs.
+/// The cgroup v2 entry has no controllers.
+#[must_use]
+pub fn parse_proc_cgroup(content: &str) -> Vec<(Vec<String>, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let _hierarchy = fields.next()?;
+            let controllers = fields
+                .next()?
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(ToString::to_string)
+                .collect();
+            Some((controllers, fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Number of CPUs in a list such as `0-3,8,10-11`
+#[must_use]
+pub fn parse_cpu_list(list: &str) -> u32 {
+    list.split(',')
+        .filter_map(|range| match range.trim().split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (start.parse::<u32>().ok()?, end.parse::<u32>().ok()?);
+                Some(end.saturating_sub(start) + 1)
+            }
+            None => range.trim().parse::<u32>().ok().map(|_| 1),
+        })
+        .sum()
+}
+
+fn parse_v2_cpu_max(content: &str) -> Option<f64> {
+    let (quota, period) = content.split_once(' ')?;
+    let (quota, period) = (quota.parse::<f64>().ok()?, period.parse::<f64>().ok()?);
+    (period > 0.0).then_some(quota / period)
+}
+
+fn parse_limit(content: &str) -> Option<f64> {
+    let limit = content.parse::<u64>().ok()?;
+    (limit < V1_UNLIMITED).then_some(limit as f64)
+}
+
+fn stat_field(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        if name == key {
+            value.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+impl Cgroup {
+    #[must_use]
+    pub fn detect() -> Option<Self> {
+        Self::with_roots(Path::new(PROC_SELF_CGROUP), Path::new(CGROUP_ROOT))
+    }
+
+    #[must_use]
+    pub fn with_roots(proc_cgroup: &Path, cgroup_root: &Path) -> Option<Self> {
+        let entries = parse_proc_cgroup(&fs::read_to_string(proc_cgroup).ok()?);
+
+        let v1_dir = |controller: &str| {
+            let (controllers, path) = entries
+                .iter()
+                .find(|(controllers, _)| controllers.iter().any(|c| c == controller))?;
+            // Co-mounted controllers live in e.g. `cpu,cpuacct`, usually with symlinks per name
+            let mount = [controllers.join(","), controller.to_string()]
+                .iter()
+                .map(|name| cgroup_root.join(name))
+                .find(|mount| mount.is_dir())?;
+            Some(ControllerDir::resolve(mount, path))
+        };
+
+        let cgroup = if entries
+            .iter()
+            .any(|(controllers, _)| !controllers.is_empty())
+        {
+            Self {
+                version: CgroupVersion::V1,
+                cpu: v1_dir("cpu"),
+                cpuacct: v1_dir("cpuacct"),
+                cpuset: v1_dir("cpuset"),
+                memory: v1_dir("memory"),
+            }
+        } else {
+            let (_, path) = entries
+                .iter()
+                .find(|(controllers, _)| controllers.is_empty())?;
+            if !cgroup_root.join("cgroup.controllers").exists() {
+                return None;
+            }
+            let dir = ControllerDir::resolve(cgroup_root.to_path_buf(), path);
+            Self {
+                version: CgroupVersion::V2,
+                cpu: Some(dir.clone()),
+                cpuacct: Some(dir.clone()),
+                cpuset: Some(dir.clone()),
+                memory: Some(dir),
+            }
+        };
+
+        trace!("CGROUP successfully detected: {cgroup:?}");
+        Some(cgroup)
+    }
+
+    /// CPU cores available to the cgroup from its CFS quota and cpuset
+    #[must_use]
+    pub fn cpu_limit(&self) -> Option<f64> {
+        let quota = self.cpu.as_ref().and_then(|cpu| match self.version {
+            CgroupVersion::V2 => {
+                cpu.min_over_ancestors(|dir| parse_v2_cpu_max(&read_trimmed(dir, "cpu.max")?))
+            }
+            // Every level's quota is relative to its own period
+            CgroupVersion::V1 => cpu.min_over_ancestors(|dir| {
+                let quota = read_trimmed(dir, "cpu.cfs_quota_us")?
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|quota| *quota > 0.0)?;
+                let period = read_trimmed(dir, "cpu.cfs_period_us")?
+                    .parse::<f64>()
+                    .ok()?;
+                (period > 0.0).then_some(quota / period)
+            }),
+        });
+
+        let cpuset_file = match self.version {
+            CgroupVersion::V2 => "cpuset.cpus.effective",
+            CgroupVersion::V1 => "cpuset.cpus",
+        };
+        let cpuset = self
+            .cpuset
+            .as_ref()
+            .and_then(|cpuset| cpuset.read(cpuset_file))
+            .map(|list| f64::from(parse_cpu_list(&list)))
+            .filter(|cpus| *cpus > 0.0);
+
+        match (quota, cpuset) {
+            (Some(quota), Some(cpuset)) => Some(quota.min(cpuset)),
+            (quota, cpuset) => quota.or(cpuset),
+        }
+    }
+
+    #[must_use]
+    pub fn memory_limit(&self) -> Option<u64> {
+        let file = match self.version {
+            CgroupVersion::V2 => "memory.max",
+            CgroupVersion::V1 => "memory.limit_in_bytes",
+        };
+        self.memory
+            .as_ref()?
+            .min_over_ancestors(|dir| parse_limit(&read_trimmed(dir, file)?))
+            .map(|limit| limit as u64)
+    }
+
+    /// Memory charged to the cgroup minus inactive file cache, as `docker stats` reports it
+    #[must_use]
+    pub fn memory_used(&self) -> Option<u64> {
+        let memory = self.memory.as_ref()?;
+        let (usage_file, inactive_key) = match self.version {
+            CgroupVersion::V2 => ("memory.current", "inactive_file"),
+            CgroupVersion::V1 => ("memory.usage_in_bytes", "total_inactive_file"),
+        };
+        let usage = memory.read(usage_file)?.parse::<u64>().ok()?;
+        let inactive = memory
+            .read("memory.stat")
+            .and_then(|stat| stat_field(&stat, inactive_key))
+            .unwrap_or(0);
+        Some(usage.saturating_sub(inactive))
+    }
+
+    /// Directory holding `cpu.pressure` and friends; cgroup v1 has no PSI files
+    #[must_use]
+    pub fn pressure_dir(&self) -> Option<&Path> {
+        match self.version {
+            CgroupVersion::V2 => self.cpu.as_ref().map(|cpu| cpu.dir.as_path()),
+            CgroupVersion::V1 => None,
+        }
+    }
+
+    /// Total CPU time consumed by the cgroup in microseconds
+    #[must_use]
+    pub fn cpu_usage_usec(&self) -> Option<u64> {
+        let cpuacct = self.cpuacct.as_ref()?;
+        match self.version {
+            CgroupVersion::V2 => stat_field(&cpuacct.read("cpu.stat")?, "usage_usec"),
+            CgroupVersion::V1 => Some(cpuacct.read("cpuacct.usage")?.parse::<u64>().ok()? / 1000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CgroupUsage {
+    /// Percent of the cgroup's CPU quota
+    pub cpu_usage: Option<f64>,
+    pub memory_used: Option<u64>,
+    pub memory_limit: Option<u64>,
+}
+
+pub struct CgroupCollector {
+    cgroup: Cgroup,
+    cpu_limit: f64,
+    last_usage_usec: Option<u64>,
+    last_sample: Instant,
+}
+
+impl CgroupCollector {
+    #[must_use]
+    pub fn new(cgroup: Cgroup) -> Self {
+        // Without a quota, usage is relative to every CPU we may run on
+        let cpu_limit = cgroup.cpu_limit().unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1.0, |n| n.get() as f64)
+        });
+        Self {
+            last_usage_usec: cgroup.cpu_usage_usec(),
+            last_sample: Instant::now(),
+            cgroup,
+            cpu_limit,
+        }
+    }
+}
+
+impl Collector for CgroupCollector {
+    type Output = CgroupUsage;
+
+    fn collect(&mut self) -> CgroupUsage {
+        let usage_usec = self.cgroup.cpu_usage_usec();
+        let elapsed_usec = self.last_sample.elapsed().as_secs_f64() * 1_000_000.0;
+
+        let cpu_usage = match (self.last_usage_usec, usage_usec) {
+            (Some(prev), Some(curr)) if elapsed_usec > 0.0 => Some(
+                (curr.saturating_sub(prev) as f64 / (elapsed_usec * self.cpu_limit) * 100.0)
+                    .min(100.0),
+            ),
+            _ => None,
+        };
+        self.last_usage_usec = usage_usec;
+        self.last_sample = Instant::now();
+
+        let usage = CgroupUsage {
+            cpu_usage,
+            memory_used: self.cgroup.memory_used(),
+            memory_limit: self.cgroup.memory_limit(),
+        };
+        trace!("REALTIME CGROUP successfully retrieved: {usage:?}");
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn v1_cpu_limit_divides_each_quota_by_its_own_period() {
+        let root = tempfile::tempdir().unwrap();
+        let proc_cgroup = root.path().join("cgroup");
+        write(&proc_cgroup, "4:cpu,cpuacct:/kubepods/pod1/ctr\n");
+
+        let mount = root.path().join("fs/cpu,cpuacct");
+        // 2 cores at the pod level, 3 cores on the container
+        write(&mount.join("kubepods/pod1/cpu.cfs_quota_us"), "100000\n");
+        write(&mount.join("kubepods/pod1/cpu.cfs_period_us"), "50000\n");
+        write(
+            &mount.join("kubepods/pod1/ctr/cpu.cfs_quota_us"),
+            "300000\n",
+        );
+        write(
+            &mount.join("kubepods/pod1/ctr/cpu.cfs_period_us"),
+            "100000\n",
+        );
+        write(&mount.join("cpu.cfs_quota_us"), "-1\n");
+        write(&mount.join("cpu.cfs_period_us"), "100000\n");
+
+        let cgroup = Cgroup::with_roots(&proc_cgroup, &root.path().join("fs")).unwrap();
+        assert_eq!(cgroup.version, CgroupVersion::V1);
+        assert_eq!(cgroup.cpu_limit(), Some(2.0));
+    }
+
+    #[test]
+    fn v2_limits_take_the_smallest_ancestor() {
+        let root = tempfile::tempdir().unwrap();
+        let proc_cgroup = root.path().join("cgroup");
+        write(&proc_cgroup, "0::/machine.slice/app\n");
+
+        let mount = root.path().join("fs");
+        write(&mount.join("cgroup.controllers"), "cpu memory\n");
+        write(&mount.join("machine.slice/cpu.max"), "150000 100000\n");
+        write(&mount.join("machine.slice/memory.max"), "max\n");
+        write(&mount.join("machine.slice/app/cpu.max"), "max 100000\n");
+        write(&mount.join("machine.slice/app/memory.max"), "536870912\n");
+
+        let cgroup = Cgroup::with_roots(&proc_cgroup, &mount).unwrap();
+        assert_eq!(cgroup.version, CgroupVersion::V2);
+        assert_eq!(cgroup.cpu_limit(), Some(1.5));
+        assert_eq!(cgroup.memory_limit(), Some(536_870_912));
+    }
+}
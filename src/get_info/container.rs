@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::Path;
+
+/// `container=` in the environment of pid 1, set by systemd-nspawn, LXC, Podman and others
+fn environ_container(environ: &[u8]) -> Option<String> {
+    environ
+        .split(|byte| *byte == 0)
+        .filter_map(|var| std::str::from_utf8(var).ok())
+        .find_map(|var| var.strip_prefix("container="))
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+}
+
+/// Container runtime named in the cgroup paths of pid 1
+#[must_use]
+pub fn cgroup_container(cgroup: &str) -> Option<&'static str> {
+    let paths = cgroup.lines().filter_map(|line| line.splitn(3, ':').nth(2));
+    for path in paths {
+        if path.contains("kubepods") {
+            return Some("kubernetes");
+        }
+        if path.contains("libpod") {
+            return Some("podman");
+        }
+        if path.contains("/docker") || path.contains("docker-") {
+            return Some("docker");
+        }
+        if path.starts_with("/lxc") || path.contains("lxc.payload") {
+            return Some("lxc");
+        }
+        if path.contains("machine.slice/machine-") {
+            return Some("systemd-nspawn");
+        }
+    }
+    None
+}
+
+/// Detect the container or sandbox we run in, with all paths below `root`
+pub fn detect_container_with_root(root: &Path) -> Option<String> {
+    let read = |path: &str| fs::read_to_string(root.join(path)).ok();
+
+    // WSL is the host of its distributions, not a container, but it is the
+    // layer users care about and nothing else reports it reliably
+    if read("proc/sys/kernel/osrelease").is_some_and(|release| {
+        let release = release.to_lowercase();
+        release.contains("microsoft") || release.contains("wsl")
+    }) {
+        return Some("wsl".to_string());
+    }
+
+    if let Some(name) = fs::read(root.join("proc/1/environ"))
+        .ok()
+        .and_then(|environ| environ_container(&environ))
+    {
+        // Podman sets container=oci; its marker file is more specific
+        if name != "oci" || !root.join("run/.containerenv").exists() {
+            return Some(name);
+        }
+    }
+
+    if root.join("run/.containerenv").exists() {
+        return Some("podman".to_string());
+    }
+    if root.join(".dockerenv").exists() {
+        return Some("docker".to_string());
+    }
+
+    read("proc/1/cgroup")
+        .as_deref()
+        .and_then(cgroup_container)
+        .map(ToString::to_string)
+}
+
+#[must_use]
+pub fn detect_container() -> Option<String> {
+    detect_container_with_root(Path::new("/"))
+}
+
+/// Hypervisor from the DMI strings the firmware exposes in `/sys/class/dmi/id`.
+/// Unlike `heim_virt`, this still works when a container was found first.
+#[must_use]
+pub fn detect_hypervisor_dmi_with_root(root: &Path) -> Option<&'static str> {
+    const VENDORS: &[(&str, &str)] = &[
+        ("kvm", "kvm"),
+        ("qemu", "qemu"),
+        ("vmware", "vmware"),
+        ("virtualbox", "oracle"),
+        ("innotek", "oracle"),
+        ("xen", "xen"),
+        ("bochs", "bochs"),
+        ("parallels", "parallels"),
+        ("bhyve", "bhyve"),
+        ("amazon ec2", "amazon"),
+        ("google compute engine", "google"),
+        // Hyper-V guests; the vendor alone also matches Surface hardware
+        ("virtual machine", "hyperv"),
+    ];
+
+    // Bare-metal cloud instances keep their vendor strings, so on x86 trust
+    // them only when the CPU says it runs under a hypervisor
+    let cpuinfo = fs::read_to_string(root.join("proc/cpuinfo")).unwrap_or_default();
+    if let Some(flags) = cpuinfo.lines().find(|line| line.starts_with("flags"))
+        && !flags.split_whitespace().any(|flag| flag == "hypervisor")
+    {
+        return None;
+    }
+
+    ["sys_vendor", "product_name", "bios_vendor", "board_vendor"]
+        .iter()
+        .filter_map(|file| fs::read_to_string(root.join("sys/class/dmi/id").join(file)).ok())
+        .find_map(|value| {
+            let value = value.to_lowercase();
+            VENDORS
+                .iter()
+                .find(|(pattern, _)| value.contains(pattern))
+                .map(|(_, name)| *name)
+        })
+}
+
+#[must_use]
+pub fn detect_hypervisor_dmi() -> Option<&'static str> {
+    detect_hypervisor_dmi_with_root(Path::new("/"))
+}
+
+/// `kvm + docker`, `kvm`, `docker` or `unknown` when neither layer was found
+#[must_use]
+pub fn format_virtualization(hypervisor: Option<&str>, container: Option<&str>) -> String {
+    match (hypervisor, container) {
+        (Some(hypervisor), Some(container)) => format!("{hypervisor} + {container}"),
+        (Some(layer), None) | (None, Some(layer)) => layer.to_string(),
+        (None, None) => "unknown".to_string(),
+    }
+}
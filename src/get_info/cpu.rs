@@ -1,8 +1,11 @@
 use crate::data_struct::Cpu;
+use crate::get_info::Collector;
 use log::trace;
 use std::collections::HashSet;
-use sysinfo::System;
+use std::fs;
+use sysinfo::{CpuRefreshKind, System};
 
+#[must_use]
 pub fn arch() -> String {
     let arch = std::env::consts::ARCH.to_string();
     trace!("ARCH successfully retrieved: {arch}");
@@ -15,6 +18,7 @@ pub struct CPUInfoWithOutUsage {
     pub cores: u16,
 }
 
+#[must_use]
 pub fn cpu_info_without_usage(sysinfo_sys: &System) -> CPUInfoWithOutUsage {
     let cores = u16::try_from(sysinfo_sys.cpus().len()).unwrap_or(0);
     let mut hashset = HashSet::new();
@@ -35,6 +39,7 @@ pub fn cpu_info_without_usage(sysinfo_sys: &System) -> CPUInfoWithOutUsage {
     cpu_info
 }
 
+#[must_use]
 pub fn realtime_cpu(sysinfo_sys: &System) -> Cpu {
     let cpus = sysinfo_sys.cpus();
     let mut avg = 0.0;
@@ -43,7 +48,273 @@ pub fn realtime_cpu(sysinfo_sys: &System) -> Cpu {
     }
     let avg = f64::from(avg) / cpus.len() as f64;
 
-    let cpu = Cpu { usage: avg };
+    let cpu = Cpu {
+        usage: avg,
+        per_core: cpus.iter().map(|cpu| f64::from(cpu.cpu_usage())).collect(),
+        ..Cpu::default()
+    };
     trace!("REALTIME CPU successfully retrieved: {cpu:?}");
     cpu
 }
+
+/// Cumulative jiffies of one `cpu` line in /proc/stat
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTimes {
+    pub user: u64,
+    pub nice: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub iowait: u64,
+    pub irq: u64,
+    pub softirq: u64,
+    pub steal: u64,
+}
+
+impl CpuTimes {
+    /// Guest time is already accounted in `user` and `nice`
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+
+    /// Counters elapsed since `prev`; a counter reset yields 0 instead of wrapping
+    #[must_use]
+    pub fn delta(&self, prev: &CpuTimes) -> CpuTimes {
+        CpuTimes {
+            user: self.user.saturating_sub(prev.user),
+            nice: self.nice.saturating_sub(prev.nice),
+            system: self.system.saturating_sub(prev.system),
+            idle: self.idle.saturating_sub(prev.idle),
+            iowait: self.iowait.saturating_sub(prev.iowait),
+            irq: self.irq.saturating_sub(prev.irq),
+            softirq: self.softirq.saturating_sub(prev.softirq),
+            steal: self.steal.saturating_sub(prev.steal),
+        }
+    }
+
+    /// Busy share of a delta in percent
+    #[must_use]
+    pub fn usage(&self) -> f64 {
+        self.percent(self.total() - self.idle - self.iowait)
+    }
+
+    fn percent(&self, part: u64) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => part as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+/// The aggregate `cpu` line and the per-core `cpuN` lines of /proc/stat
+#[derive(Debug, Clone, Default)]
+pub struct ProcStat {
+    pub total: CpuTimes,
+    /// Keyed by core index; offline cores have no line
+    pub cores: Vec<(u32, CpuTimes)>,
+}
+
+#[must_use]
+pub fn parse_proc_stat(content: &str) -> Option<ProcStat> {
+    let mut total = None;
+    let mut cores = Vec::new();
+
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(name) = fields.next().and_then(|name| name.strip_prefix("cpu")) else {
+            continue;
+        };
+        let values: Vec<u64> = fields.map(|v| v.parse::<u64>().unwrap_or(0)).collect();
+        // Kernels before 2.6.11 have no steal column
+        let field = |i: usize| values.get(i).copied().unwrap_or(0);
+        let times = CpuTimes {
+            user: field(0),
+            nice: field(1),
+            system: field(2),
+            idle: field(3),
+            iowait: field(4),
+            irq: field(5),
+            softirq: field(6),
+            steal: field(7),
+        };
+
+        if name.is_empty() {
+            total = Some(times);
+        } else if let Ok(index) = name.parse::<u32>() {
+            cores.push((index, times));
+        }
+    }
+
+    Some(ProcStat {
+        total: total?,
+        cores,
+    })
+}
+
+#[must_use]
+pub fn read_proc_stat() -> Option<ProcStat> {
+    parse_proc_stat(&fs::read_to_string("/proc/stat").ok()?)
+}
+
+/// Fill the time breakdown and per-core usage of `cpu` from two /proc/stat snapshots
+pub fn apply_proc_stat(cpu: &mut Cpu, prev: &ProcStat, curr: &ProcStat) {
+    let delta = curr.total.delta(&prev.total);
+    cpu.user = delta.percent(delta.user);
+    cpu.system = delta.percent(delta.system);
+    cpu.nice = delta.percent(delta.nice);
+    cpu.iowait = delta.percent(delta.iowait);
+    cpu.irq = delta.percent(delta.irq);
+    cpu.softirq = delta.percent(delta.softirq);
+    cpu.steal = delta.percent(delta.steal);
+
+    cpu.per_core = curr
+        .cores
+        .iter()
+        .map(|(index, times)| {
+            prev.cores
+                .iter()
+                .find(|(prev_index, _)| prev_index == index)
+                .map_or(0.0, |(_, prev_times)| times.delta(prev_times).usage())
+        })
+        .collect();
+}
+
+pub struct CpuCollector {
+    sysinfo_sys: System,
+    last_proc_stat: Option<ProcStat>,
+}
+
+impl CpuCollector {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut sysinfo_sys = System::new();
+        sysinfo_sys.refresh_cpu_list(
+            CpuRefreshKind::nothing()
+                .without_cpu_usage()
+                .without_frequency(),
+        );
+        Self {
+            sysinfo_sys,
+            last_proc_stat: read_proc_stat(),
+        }
+    }
+}
+
+impl Default for CpuCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Collector for CpuCollector {
+    type Output = Cpu;
+
+    fn collect(&mut self) -> Cpu {
+        self.sysinfo_sys
+            .refresh_cpu_specifics(CpuRefreshKind::everything().without_frequency());
+        let mut cpu = realtime_cpu(&self.sysinfo_sys);
+
+        // Only Linux has /proc/stat; elsewhere per-core usage comes from sysinfo
+        let proc_stat = read_proc_stat();
+        if let (Some(prev), Some(curr)) = (&self.last_proc_stat, &proc_stat) {
+            apply_proc_stat(&mut cpu, prev, curr);
+            trace!("REALTIME CPU TIMES successfully retrieved: {cpu:?}");
+        }
+        self.last_proc_stat = proc_stat;
+        cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Columns: `user nice system idle iowait irq softirq steal guest guest_nice`
+    const PROC_STAT_1: &str = "\
+cpu  1000 100 500 8000 200 10 40 50 300 0
+cpu0 500 50 250 4000 100 5 20 25 150 0
+cpu1 500 50 250 4000 100 5 20 25 150 0
+intr 123456 0 0
+ctxt 987654
+btime 1700000000
+";
+
+    /// 2000 jiffies later; cpu1 went offline and cpu2 came online
+    const PROC_STAT_2: &str = "\
+cpu  1600 100 800 8800 400 20 80 100 500 0
+cpu0 800 50 400 4400 200 10 40 50 250 0
+cpu2 100 0 50 900 0 0 0 0 0 0
+intr 124000 0 0
+ctxt 990000
+btime 1700000000
+";
+
+    fn assert_percent(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn parse_proc_stat_reads_aggregate_and_cores() {
+        let stat = parse_proc_stat(PROC_STAT_1).unwrap();
+        assert_eq!(stat.total.user, 1000);
+        assert_eq!(stat.total.steal, 50);
+        // guest is already part of user, so it is left out of the total
+        assert_eq!(stat.total.total(), 9900);
+        let indexes: Vec<u32> = stat.cores.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indexes, [0, 1]);
+
+        // Kernels before 2.6.11 have no steal column
+        let old = parse_proc_stat("cpu 10 0 5 85 0 0 0\n").unwrap();
+        assert_eq!(old.total.steal, 0);
+        assert_eq!(old.total.total(), 100);
+
+        assert!(parse_proc_stat("cpu0 1 2 3 4\nintr 1\n").is_none());
+    }
+
+    #[test]
+    fn apply_proc_stat_splits_the_delta_into_percentages() {
+        let prev = parse_proc_stat(PROC_STAT_1).unwrap();
+        let curr = parse_proc_stat(PROC_STAT_2).unwrap();
+        let mut cpu = Cpu::default();
+
+        apply_proc_stat(&mut cpu, &prev, &curr);
+
+        assert_percent(cpu.user, 30.0);
+        assert_percent(cpu.nice, 0.0);
+        assert_percent(cpu.system, 15.0);
+        assert_percent(cpu.iowait, 10.0);
+        assert_percent(cpu.irq, 0.5);
+        assert_percent(cpu.softirq, 2.0);
+        assert_percent(cpu.steal, 2.5);
+        // Idle and iowait are the only idle time; steal counts as busy
+        assert_percent(curr.total.delta(&prev.total).usage(), 50.0);
+        let sum = cpu.user + cpu.nice + cpu.system + cpu.iowait + cpu.irq + cpu.softirq + cpu.steal;
+        assert_percent(sum, 60.0);
+    }
+
+    #[test]
+    fn apply_proc_stat_reports_new_cores_as_idle_until_their_second_sample() {
+        let prev = parse_proc_stat(PROC_STAT_1).unwrap();
+        let curr = parse_proc_stat(PROC_STAT_2).unwrap();
+        let mut cpu = Cpu::default();
+
+        apply_proc_stat(&mut cpu, &prev, &curr);
+
+        // cpu0 went from 4950 to 5950 jiffies, 500 of them idle or iowait
+        assert_eq!(cpu.per_core.len(), 2);
+        assert_percent(cpu.per_core[0], 50.0);
+        assert_percent(cpu.per_core[1], 0.0);
+
+        // The same snapshot twice has no elapsed time at all
+        apply_proc_stat(&mut cpu, &curr, &curr);
+        assert_percent(cpu.user, 0.0);
+        assert_percent(cpu.per_core[0], 0.0);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::data_struct::CpuInventory;
+use log::trace;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// One `processor` block of /proc/cpuinfo, reduced to what the inventory needs
+#[derive(Debug, Clone, Default)]
+pub struct CpuinfoProcessor {
+    pub physical_id: Option<u32>,
+    pub core_id: Option<u32>,
+    pub model_name: String,
+    /// `flags` on x86, `Features` on ARM
+    pub flags: Vec<String>,
+}
+
+#[must_use]
+pub fn parse_cpuinfo(content: &str) -> Vec<CpuinfoProcessor> {
+    content
+        .split("\n\n")
+        .filter(|block| block.lines().any(|line| line.starts_with("processor")))
+        .map(|block| {
+            let mut processor = CpuinfoProcessor::default();
+            for line in block.lines() {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+                match key.trim() {
+                    "physical id" => processor.physical_id = value.parse().ok(),
+                    "core id" => processor.core_id = value.parse().ok(),
+                    "model name" => processor.model_name = value.to_string(),
+                    "flags" | "Features" => {
+                        processor.flags = value.split_whitespace().map(str::to_string).collect();
+                    }
+                    _ => {}
+                }
+            }
+            processor
+        })
+        .collect()
+}
+
+/// Sizes such as `32K`, `2048K` or `32M` from the sysfs cache directory, in bytes
+fn parse_cache_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 1024),
+        'M' => (&size[..size.len() - 1], 1024 * 1024),
+        'G' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    Some(number.parse::<u64>().ok()? * multiplier)
+}
+
+/// The nominal frequency in a model name such as `Intel(R) Xeon(R) CPU E5-2680 v4 @ 2.40GHz`, in MHz
+fn model_name_frequency(model_name: &str) -> Option<u64> {
+    let (_, frequency) = model_name.rsplit_once('@')?;
+    let frequency = frequency.trim().strip_suffix("GHz")?;
+    Some((frequency.trim().parse::<f64>().ok()? * 1000.0) as u64)
+}
+
+/// Numbered entries such as `cpu0` or `node1` in `dir`
+fn numbered_entries(dir: &Path, prefix: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let number = name.strip_prefix(prefix)?;
+            (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit())).then_some(name)
+        })
+        .collect()
+}
+
+/// Build the inventory from `<root>/proc/cpuinfo` and `<root>/sys/devices/system`
+#[must_use]
+pub fn cpu_inventory_with_root(root: &Path) -> CpuInventory {
+    let read = |path: &Path| Some(fs::read_to_string(path).ok()?.trim().to_string());
+    let sys_cpu = root.join("sys/devices/system/cpu");
+
+    let processors = fs::read_to_string(root.join("proc/cpuinfo"))
+        .map(|content| parse_cpuinfo(&content))
+        .unwrap_or_default();
+
+    // sysfs topology works on every architecture; /proc/cpuinfo only has it on x86
+    let cpus = numbered_entries(&sys_cpu, "cpu");
+    let topology: Vec<(String, String)> = cpus
+        .iter()
+        .filter_map(|cpu| {
+            let dir = sys_cpu.join(cpu).join("topology");
+            Some((
+                read(&dir.join("physical_package_id"))?,
+                read(&dir.join("core_id"))?,
+            ))
+        })
+        .collect();
+    let (logical_cores, physical_cores, sockets) = if topology.is_empty() {
+        let cores: HashSet<(Option<u32>, Option<u32>)> = processors
+            .iter()
+            .map(|processor| (processor.physical_id, processor.core_id))
+            .collect();
+        let sockets: HashSet<Option<u32>> = processors
+            .iter()
+            .map(|processor| processor.physical_id)
+            .collect();
+        (processors.len(), cores.len(), sockets.len())
+    } else {
+        let sockets: HashSet<&String> = topology.iter().map(|(package, _)| package).collect();
+        let cores: HashSet<&(String, String)> = topology.iter().collect();
+        (topology.len(), cores.len(), sockets.len())
+    };
+
+    let cpufreq = sys_cpu.join("cpu0/cpufreq");
+    let khz_to_mhz = |file: &str| {
+        read(&cpufreq.join(file))?
+            .parse::<u64>()
+            .ok()
+            .map(|khz| khz / 1000)
+    };
+    let base_frequency = khz_to_mhz("base_frequency").or_else(|| {
+        processors
+            .first()
+            .and_then(|processor| model_name_frequency(&processor.model_name))
+    });
+    let max_frequency = khz_to_mhz("cpuinfo_max_freq");
+
+    let mut inventory = CpuInventory {
+        logical_cores: logical_cores as u64,
+        physical_cores: physical_cores as u64,
+        sockets: sockets as u64,
+        base_frequency,
+        max_frequency,
+        numa_nodes: (numbered_entries(&root.join("sys/devices/system/node"), "node").len() as u64)
+            .max(1),
+        ..CpuInventory::default()
+    };
+
+    for index in numbered_entries(&sys_cpu.join("cpu0/cache"), "index") {
+        let dir = sys_cpu.join("cpu0/cache").join(index);
+        let size = read(&dir.join("size")).and_then(|size| parse_cache_size(&size));
+        match (
+            read(&dir.join("level")).as_deref(),
+            read(&dir.join("type")).as_deref(),
+        ) {
+            (Some("1"), Some("Data")) => inventory.cache_l1d = size,
+            (Some("1"), Some("Instruction")) => inventory.cache_l1i = size,
+            (Some("2"), _) => inventory.cache_l2 = size,
+            (Some("3"), _) => inventory.cache_l3 = size,
+            _ => {}
+        }
+    }
+
+    if let Some(processor) = processors.first() {
+        let has = |flag: &str| processor.flags.iter().any(|f| f == flag);
+        inventory.virtualization = if has("vmx") {
+            Some("vmx".to_string())
+        } else if has("svm") {
+            Some("svm".to_string())
+        } else {
+            None
+        };
+        inventory.aes = has("aes");
+        inventory.avx2 = has("avx2");
+        inventory.avx512 = has("avx512f");
+    }
+
+    trace!("CPU INVENTORY successfully retrieved: {inventory:?}");
+    inventory
+}
+
+#[cfg(target_os = "linux")]
+#[must_use]
+pub fn cpu_inventory(_sysinfo_sys: &sysinfo::System) -> CpuInventory {
+    cpu_inventory_with_root(Path::new("/"))
+}
+
+/// Without procfs and sysfs only the counts and frequency are known
+#[cfg(not(target_os = "linux"))]
+pub fn cpu_inventory(sysinfo_sys: &sysinfo::System) -> CpuInventory {
+    let inventory = CpuInventory {
+        logical_cores: sysinfo_sys.cpus().len() as u64,
+        physical_cores: sysinfo::System::physical_core_count().unwrap_or_default() as u64,
+        sockets: 1,
+        base_frequency: sysinfo_sys.cpus().first().map(sysinfo::Cpu::frequency),
+        numa_nodes: 1,
+        ..CpuInventory::default()
+    };
+    trace!("CPU INVENTORY successfully retrieved: {inventory:?}");
+    inventory
+}
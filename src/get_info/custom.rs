@@ -0,0 +1,234 @@
+use crate::config::{CustomMetricCommand, UserConfig};
+use crate::data_struct::CustomMetric;
+use crate::get_info::Collector;
+use crate::utils::{output_with_timeout, shell_command};
+use log::{debug, trace, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Parse one metric line, either `key=value` or Prometheus text such as
+/// `http_requests_total{code="200"} 1027 1395066363000`
+fn parse_metric_line(line: &str) -> Option<(String, f64)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (name, rest) = match (line.find('{'), line.find('=')) {
+        // Label values may contain spaces, so the name ends at the last brace
+        (Some(brace), Some(eq)) if eq < brace => (line.get(..eq)?, line.get(eq + 1..)?),
+        (Some(_), _) => {
+            let end = line.rfind('}')? + 1;
+            (line.get(..end)?, line.get(end..)?)
+        }
+        (None, Some(eq)) => (line.get(..eq)?, line.get(eq + 1..)?),
+        (None, None) => line.split_once(char::is_whitespace)?,
+    };
+
+    // A trailing timestamp is ignored
+    let value = rest.split_whitespace().next()?.parse::<f64>().ok()?;
+    let name = name.trim();
+    // JSON has no NaN or infinity
+    (!name.is_empty() && value.is_finite()).then(|| (name.to_string(), value))
+}
+
+/// Metrics in `key=value` or Prometheus text format; lines that are neither are skipped
+pub fn parse_metrics(text: &str, source: &str) -> Vec<CustomMetric> {
+    text.lines()
+        .filter_map(parse_metric_line)
+        .map(|(name, value)| CustomMetric {
+            name,
+            value,
+            source: source.to_string(),
+        })
+        .collect()
+}
+
+/// Every `*.prom` file in `dir`, named after the file
+#[must_use]
+pub fn read_textfile_dir(dir: &Path) -> Vec<CustomMetric> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "prom"))
+        .collect();
+    files.sort();
+
+    files
+        .iter()
+        .filter_map(|path| {
+            let content = fs::read_to_string(path).ok()?;
+            let source = path.file_name()?.to_string_lossy();
+            Some(parse_metrics(&content, &source))
+        })
+        .flatten()
+        .collect()
+}
+
+/// Run `command` through the shell, killing it once `timeout` has passed
+///
+/// # Errors
+///
+/// When the command cannot be started, times out or exits unsuccessfully.
+pub fn run_command(command: &str, timeout: Duration) -> Result<Vec<CustomMetric>, String> {
+    let output = output_with_timeout(shell_command(command), timeout)?;
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+    Ok(parse_metrics(
+        &String::from_utf8_lossy(&output.stdout),
+        command,
+    ))
+}
+
+/// Runs each command on its own thread at its own interval; the latest output
+/// of every command is kept until the next run replaces it.
+pub struct CustomMetricsCollector {
+    /// Latest metrics per command, in config order
+    results: Arc<Mutex<Vec<Vec<CustomMetric>>>>,
+    textfile_dir: Option<PathBuf>,
+}
+
+impl CustomMetricsCollector {
+    #[must_use]
+    pub fn new(commands: &[CustomMetricCommand], textfile_dir: Option<PathBuf>) -> Self {
+        let results = Arc::new(Mutex::new(vec![Vec::new(); commands.len()]));
+        for (index, command) in commands.iter().enumerate() {
+            spawn_runner(index, command.clone(), Arc::downgrade(&results));
+        }
+        Self {
+            results,
+            textfile_dir,
+        }
+    }
+
+    /// `None` when no command or textfile directory is configured
+    pub fn from_config(config: &UserConfig) -> Option<Self> {
+        if config.custom_metric_commands.is_empty() && config.custom_metrics_textfile_dir.is_none()
+        {
+            return None;
+        }
+        Some(Self::new(
+            &config.custom_metric_commands,
+            config
+                .custom_metrics_textfile_dir
+                .as_ref()
+                .map(PathBuf::from),
+        ))
+    }
+}
+
+/// The thread exits once the collector owning `results` is dropped
+fn spawn_runner(
+    index: usize,
+    command: CustomMetricCommand,
+    results: Weak<Mutex<Vec<Vec<CustomMetric>>>>,
+) {
+    thread::spawn(move || {
+        let interval = Duration::from_secs(command.interval);
+        let timeout = Duration::from_secs(command.timeout);
+        loop {
+            let started = Instant::now();
+            let metrics = match run_command(&command.command, timeout) {
+                Ok(metrics) => {
+                    debug!(
+                        "Custom metric command `{}` returned {} values",
+                        command.command,
+                        metrics.len()
+                    );
+                    metrics
+                }
+                Err(e) => {
+                    warn!("Custom metric command `{}` {e}", command.command);
+                    Vec::new()
+                }
+            };
+
+            let Some(results) = results.upgrade() else {
+                return;
+            };
+            if let Ok(mut results) = results.lock() {
+                results[index] = metrics;
+            }
+            drop(results);
+
+            thread::sleep(interval.saturating_sub(started.elapsed()));
+        }
+    });
+}
+
+impl Collector for CustomMetricsCollector {
+    type Output = Vec<CustomMetric>;
+
+    fn collect(&mut self) -> Vec<CustomMetric> {
+        let mut metrics: Vec<CustomMetric> = self
+            .results
+            .lock()
+            .map(|results| results.iter().flatten().cloned().collect())
+            .unwrap_or_default();
+        if let Some(dir) = &self.textfile_dir {
+            metrics.extend(read_textfile_dir(dir));
+        }
+        trace!("REALTIME CUSTOM METRICS successfully retrieved: {metrics:?}");
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_key_value_and_prometheus_lines() {
+        let metrics = parse_metrics(
+            "# HELP requests Total requests\nqueue=12\nrequests{code=\"200\",path=\"/a b\"} 1027 1395066363000\nbroken\nnan=NaN\n",
+            "test",
+        );
+        let values: Vec<(&str, f64)> = metrics
+            .iter()
+            .map(|metric| (metric.name.as_str(), metric.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("queue", 12.0),
+                ("requests{code=\"200\",path=\"/a b\"}", 1027.0)
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn background_job_holding_stdout_is_killed_at_the_deadline() {
+        let started = Instant::now();
+        let result = run_command("echo queue=1; sleep 30 &", Duration::from_secs(1));
+        assert_eq!(result.unwrap_err(), "timed out after 1s");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipeline_is_killed_with_the_shell() {
+        let marker = std::env::temp_dir().join(format!("komari-pipeline-{}", std::process::id()));
+        let command = format!("sleep 2 | (sleep 2; touch {})", marker.display());
+        assert!(run_command(&command, Duration::from_millis(500)).is_err());
+        thread::sleep(Duration::from_secs(3));
+        assert!(!marker.exists(), "pipeline outlived its timeout");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detached_background_job_does_not_hold_up_the_result() {
+        let result = run_command(
+            "echo queue=1; sleep 30 >/dev/null 2>&1 &",
+            Duration::from_secs(5),
+        );
+        assert_eq!(result.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::data_struct::DiskIo;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::BuildHasher;
+use std::path::Path;
+
+/// Kernel sectors in /proc/diskstats are always 512 bytes
+const SECTOR_SIZE: u64 = 512;
+
+/// Cumulative counters of one line in /proc/diskstats
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiskStats {
+    pub reads: u64,
+    pub sectors_read: u64,
+    pub read_ms: u64,
+    pub writes: u64,
+    pub sectors_written: u64,
+    pub write_ms: u64,
+}
+
+impl DiskStats {
+    /// Counters elapsed since `prev`; a counter reset yields 0 instead of wrapping
+    #[must_use]
+    pub fn delta(&self, prev: &DiskStats) -> DiskStats {
+        DiskStats {
+            reads: self.reads.saturating_sub(prev.reads),
+            sectors_read: self.sectors_read.saturating_sub(prev.sectors_read),
+            read_ms: self.read_ms.saturating_sub(prev.read_ms),
+            writes: self.writes.saturating_sub(prev.writes),
+            sectors_written: self.sectors_written.saturating_sub(prev.sectors_written),
+            write_ms: self.write_ms.saturating_sub(prev.write_ms),
+        }
+    }
+
+    fn add(&mut self, other: &DiskStats) {
+        self.reads += other.reads;
+        self.sectors_read += other.sectors_read;
+        self.read_ms += other.read_ms;
+        self.writes += other.writes;
+        self.sectors_written += other.sectors_written;
+        self.write_ms += other.write_ms;
+    }
+
+    /// Turn a delta over `elapsed` seconds into rates
+    #[must_use]
+    pub fn to_disk_io(&self, name: String, elapsed: f64) -> DiskIo {
+        let ios = self.reads + self.writes;
+        DiskIo {
+            name,
+            read_speed: (self.sectors_read as f64 * SECTOR_SIZE as f64 / elapsed) as u64,
+            write_speed: (self.sectors_written as f64 * SECTOR_SIZE as f64 / elapsed) as u64,
+            read_iops: self.reads as f64 / elapsed,
+            write_iops: self.writes as f64 / elapsed,
+            await_ms: if ios == 0 {
+                0.0
+            } else {
+                (self.read_ms + self.write_ms) as f64 / ios as f64
+            },
+        }
+    }
+}
+
+/// Parse the content of /proc/diskstats into counters keyed by device name
+#[must_use]
+pub fn parse_diskstats(content: &str) -> HashMap<String, DiskStats> {
+    let mut stats = HashMap::new();
+
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // major minor name + at least 11 counters (kernels < 4.18 have exactly 11)
+        if fields.len() < 14 {
+            continue;
+        }
+        let field = |i: usize| fields[i].parse::<u64>().unwrap_or(0);
+        stats.insert(
+            fields[2].to_string(),
+            DiskStats {
+                reads: field(3),
+                sectors_read: field(5),
+                read_ms: field(6),
+                writes: field(7),
+                sectors_written: field(9),
+                write_ms: field(10),
+            },
+        );
+    }
+
+    stats
+}
+
+#[must_use]
+pub fn read_diskstats() -> HashMap<String, DiskStats> {
+    fs::read_to_string("/proc/diskstats")
+        .map(|content| parse_diskstats(&content))
+        .unwrap_or_default()
+}
+
+/// Map a mount's source device (`/dev/sda1`, `/dev/mapper/vg-root`) to its
+/// name in /proc/diskstats (`sda1`, `dm-0`)
+#[must_use]
+pub fn diskstats_device_name(device: &Path) -> String {
+    let resolved = fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+    resolved
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Per-device and aggregate I/O rates for `devices` between two snapshots
+#[must_use]
+pub fn disk_io_rates<S: BuildHasher>(
+    devices: &[String],
+    prev: &HashMap<String, DiskStats, S>,
+    curr: &HashMap<String, DiskStats, S>,
+    elapsed: f64,
+) -> (DiskIo, Vec<DiskIo>) {
+    let mut total = DiskStats::default();
+    let mut per_device = Vec::new();
+
+    if elapsed > 0.0 {
+        for device in devices {
+            let (Some(prev), Some(curr)) = (prev.get(device), curr.get(device)) else {
+                continue;
+            };
+            let delta = curr.delta(prev);
+            total.add(&delta);
+            per_device.push(delta.to_disk_io(device.clone(), elapsed));
+        }
+    }
+
+    let aggregate = total.to_disk_io("total".to_string(), elapsed.max(f64::MIN_POSITIVE));
+    (aggregate, per_device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4.18+ line for the whole disk, two partitions of it, a device-mapper
+    /// volume and an old 11-counter loop device
+    const DISKSTATS_1: &str = "\
+   8       0 sda 3000 10 80000 1500 2000 20 40000 3000 0 4000 4500 0 0 0 0
+   8       1 sda1 1000 5 20000 500 1000 10 10000 1000 0 1500 1500 0 0 0 0
+   8       2 sda2 2000 5 60000 1000 1000 10 30000 2000 0 2500 3000 0 0 0 0
+ 253       0 dm-0 500 0 8000 250 400 0 4000 150 0 300 400 0 0 0 0
+   7       0 loop0 10 0 80 1 0 0 0 0 0 1 1
+";
+
+    /// Two seconds later; dm-0 was re-created and its counters started over
+    const DISKSTATS_2: &str = "\
+   8       0 sda 3400 10 96000 1700 2100 20 44000 3300 0 4200 5000 0 0 0 0
+   8       1 sda1 1100 5 24000 550 1100 10 14000 1300 0 1600 1850 0 0 0 0
+   8       2 sda2 2300 5 72000 1150 1000 10 30000 2000 0 2600 3150 0 0 0 0
+ 253       0 dm-0 20 0 160 10 0 0 0 0 0 10 10 0 0 0 0
+   7       0 loop0 10 0 80 1 0 0 0 0 0 1 1
+";
+
+    #[test]
+    fn parse_diskstats_reads_old_and_new_layouts() {
+        let stats = parse_diskstats(DISKSTATS_1);
+        assert_eq!(stats.len(), 5);
+
+        let sda1 = stats["sda1"];
+        assert_eq!(sda1.reads, 1000);
+        assert_eq!(sda1.sectors_read, 20000);
+        assert_eq!(sda1.read_ms, 500);
+        assert_eq!(sda1.writes, 1000);
+        assert_eq!(sda1.sectors_written, 10000);
+        assert_eq!(sda1.write_ms, 1000);
+
+        assert_eq!(stats["loop0"].sectors_read, 80);
+        assert!(parse_diskstats("   8       0 sda 1 2 3\n").is_empty());
+    }
+
+    #[test]
+    fn disk_io_rates_only_counts_listed_partitions() {
+        let prev = parse_diskstats(DISKSTATS_1);
+        let curr = parse_diskstats(DISKSTATS_2);
+        let devices = vec!["sda1".to_string(), "sda2".to_string()];
+
+        let (total, per_device) = disk_io_rates(&devices, &prev, &curr, 2.0);
+
+        // sda1: 4000 sectors read and written, 100 reads and writes in 2 s
+        assert_eq!(per_device.len(), 2);
+        assert_eq!(per_device[0].name, "sda1");
+        assert_eq!(per_device[0].read_speed, 4000 * 512 / 2);
+        assert_eq!(per_device[0].write_speed, 4000 * 512 / 2);
+        assert!((per_device[0].read_iops - 50.0).abs() < f64::EPSILON);
+        assert!((per_device[0].write_iops - 50.0).abs() < f64::EPSILON);
+        // 50 ms reading plus 300 ms writing over 200 I/Os
+        assert!((per_device[0].await_ms - 1.75).abs() < f64::EPSILON);
+
+        // The whole disk `sda` is not listed, so its I/O is not counted twice
+        assert_eq!(total.name, "total");
+        assert_eq!(total.read_speed, (4000 + 12000) * 512 / 2);
+        assert_eq!(total.write_speed, 4000 * 512 / 2);
+        assert!((total.read_iops - 200.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn disk_io_rates_treats_a_counter_reset_as_idle() {
+        let prev = parse_diskstats(DISKSTATS_1);
+        let curr = parse_diskstats(DISKSTATS_2);
+        let devices = vec!["dm-0".to_string(), "nvme0n1".to_string()];
+
+        let (total, per_device) = disk_io_rates(&devices, &prev, &curr, 2.0);
+
+        // nvme0n1 is missing from both snapshots and is skipped
+        assert_eq!(per_device.len(), 1);
+        assert_eq!(per_device[0].read_speed, 0);
+        assert_eq!(per_device[0].write_speed, 0);
+        assert!(per_device[0].read_iops.abs() < f64::EPSILON);
+        assert_eq!(total.read_speed, 0);
+    }
+
+    #[test]
+    fn disk_io_rates_reports_nothing_for_the_first_sample() {
+        let curr = parse_diskstats(DISKSTATS_1);
+        let devices = vec!["sda1".to_string()];
+
+        let (total, per_device) = disk_io_rates(&devices, &curr, &curr, 0.0);
+
+        assert!(per_device.is_empty());
+        assert_eq!(total.read_speed, 0);
+        assert!(total.await_ms.abs() < f64::EPSILON);
+    }
+}
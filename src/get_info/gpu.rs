@@ -0,0 +1,510 @@
+use crate::config::UserConfig;
+use crate::data_struct::{Gpu, GpuDetail};
+use crate::get_info::Collector;
+use crate::utils::output_with_timeout;
+use log::{trace, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+const DRM_ROOT: &str = "/sys/class/drm";
+const PCI_IDS_PATHS: &[&str] = &[
+    "/usr/share/hwdata/pci.ids",
+    "/usr/share/misc/pci.ids",
+    "/usr/share/pci.ids",
+];
+const NVIDIA_VENDOR_ID: u16 = 0x10de;
+const NVIDIA_SMI_QUERY: &str =
+    "--query-gpu=name,utilization.gpu,memory.used,memory.total,temperature.gpu";
+/// nvidia-smi hangs when the driver is wedged
+const NVIDIA_SMI_TIMEOUT: Duration = Duration::from_secs(10);
+/// Seconds, same as the config default
+const DEFAULT_NVIDIA_SMI_INTERVAL: u64 = 5;
+
+/// Short vendor names used when no pci.ids database is installed
+const KNOWN_VENDORS: &[(u16, &str)] = &[
+    (0x1002, "AMD"),
+    (0x10de, "NVIDIA"),
+    (0x8086, "Intel"),
+    (0x1a03, "ASPEED"),
+    (0x102b, "Matrox"),
+    (0x1013, "Cirrus Logic"),
+    (0x1234, "QEMU"),
+    (0x1af4, "Red Hat Virtio"),
+    (0x15ad, "VMware"),
+    (0x80ee, "VirtualBox"),
+    (0x1414, "Microsoft Hyper-V"),
+    (0x5143, "Qualcomm"),
+];
+
+#[derive(Debug, Clone)]
+pub struct DrmCard {
+    pub name: String,
+    pub vendor_id: Option<u16>,
+    /// `/sys/class/drm/cardN/device`
+    pub device_path: PathBuf,
+}
+
+/// Enumerate DRM cards below `drm_root` (normally `/sys/class/drm`).
+/// Connector entries such as `card0-HDMI-A-1` are skipped.
+#[must_use]
+pub fn drm_cards(drm_root: &Path) -> Vec<DrmCard> {
+    let Ok(entries) = fs::read_dir(drm_root) else {
+        return Vec::new();
+    };
+
+    let mut card_dirs: Vec<(u32, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let index = file_name
+                .to_str()?
+                .strip_prefix("card")?
+                .parse::<u32>()
+                .ok()?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    card_dirs.sort_by_key(|(index, _)| *index);
+
+    let pci_ids = PCI_IDS_PATHS
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok());
+
+    let mut cards = Vec::new();
+    let mut seen_devices = HashSet::new();
+    for (_, card_dir) in card_dirs {
+        let device_path = card_dir.join("device");
+        // Several cards (e.g. render-only nodes) may point to the same device
+        let canonical = fs::canonicalize(&device_path).unwrap_or_else(|_| device_path.clone());
+        if !seen_devices.insert(canonical) {
+            continue;
+        }
+
+        let vendor_id = read_hex_id(&device_path.join("vendor"));
+        let device_id = read_hex_id(&device_path.join("device"));
+
+        let name = match (vendor_id, device_id) {
+            (Some(vendor_id), Some(device_id)) => {
+                pci_device_name(pci_ids.as_deref(), vendor_id, device_id)
+            }
+            // Non-PCI devices (ARM SoCs) only expose their driver
+            _ => match driver_name(&device_path) {
+                Some(driver) => driver,
+                None => continue,
+            },
+        };
+
+        cards.push(DrmCard {
+            name,
+            vendor_id,
+            device_path,
+        });
+    }
+
+    trace!("DRM CARDS successfully retrieved: {cards:?}");
+    cards
+}
+
+fn read_hex_id(path: &Path) -> Option<u16> {
+    let content = fs::read_to_string(path).ok()?;
+    u16::from_str_radix(content.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+}
+
+fn driver_name(device_path: &Path) -> Option<String> {
+    let driver = fs::read_link(device_path.join("driver")).ok()?;
+    Some(driver.file_name()?.to_string_lossy().into_owned())
+}
+
+fn pci_device_name(pci_ids: Option<&str>, vendor_id: u16, device_id: u16) -> String {
+    let known_vendor = KNOWN_VENDORS
+        .iter()
+        .find(|(id, _)| *id == vendor_id)
+        .map(|(_, name)| (*name).to_string());
+
+    match pci_ids.and_then(|content| pci_ids_lookup(content, vendor_id, device_id)) {
+        Some((vendor, Some(device))) => format!("{} {device}", known_vendor.unwrap_or(vendor)),
+        Some((vendor, None)) => {
+            format!(
+                "{} [{vendor_id:04x}:{device_id:04x}]",
+                known_vendor.unwrap_or(vendor)
+            )
+        }
+        None => format!(
+            "{} [{vendor_id:04x}:{device_id:04x}]",
+            known_vendor.unwrap_or_else(|| "Unknown".to_string())
+        ),
+    }
+}
+
+/// Look up vendor and device names in the content of a pci.ids file
+#[must_use]
+pub fn pci_ids_lookup(
+    content: &str,
+    vendor_id: u16,
+    device_id: u16,
+) -> Option<(String, Option<String>)> {
+    let vendor_prefix = format!("{vendor_id:04x}  ");
+    let device_prefix = format!("\t{device_id:04x}  ");
+
+    let mut lines = content.lines();
+    let vendor = lines.find_map(|line| line.strip_prefix(&vendor_prefix))?;
+
+    for line in lines {
+        // Next vendor (or the class section) starts at column 0
+        if !line.starts_with('\t') && !line.starts_with('#') && !line.is_empty() {
+            break;
+        }
+        if let Some(device) = line.strip_prefix(&device_prefix) {
+            return Some((vendor.to_string(), Some(device.to_string())));
+        }
+    }
+
+    Some((vendor.to_string(), None))
+}
+
+/// Read amdgpu-style metrics from a card's device directory
+#[must_use]
+pub fn sysfs_gpu_detail(card: &DrmCard) -> GpuDetail {
+    let device_path = &card.device_path;
+
+    let temperature = fs::read_dir(device_path.join("hwmon"))
+        .ok()
+        .and_then(|entries| {
+            entries
+                .flatten()
+                .find_map(|hwmon| read_u64(&hwmon.path().join("temp1_input")))
+        })
+        .map_or(0.0, |millidegree| millidegree as f64 / 1000.0);
+
+    GpuDetail {
+        name: card.name.clone(),
+        utilization: read_u64(&device_path.join("gpu_busy_percent")).map_or(0.0, |v| v as f64),
+        memory_used: read_u64(&device_path.join("mem_info_vram_used")).unwrap_or(0),
+        memory_total: read_u64(&device_path.join("mem_info_vram_total")).unwrap_or(0),
+        temperature,
+    }
+}
+
+/// Parse `nvidia-smi --query-gpu=name,utilization.gpu,memory.used,memory.total,temperature.gpu
+/// --format=csv,noheader,nounits` output. Memory is reported in MiB.
+#[must_use]
+pub fn parse_nvidia_smi(output: &str) -> Vec<GpuDetail> {
+    let parse_f64 = |s: &str| s.trim().parse::<f64>().unwrap_or(0.0);
+    let parse_mib = |s: &str| s.trim().parse::<u64>().unwrap_or(0) * 1024 * 1024;
+
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != 5 {
+                return None;
+            }
+            Some(GpuDetail {
+                name: fields[0].trim().to_string(),
+                utilization: parse_f64(fields[1]),
+                memory_used: parse_mib(fields[2]),
+                memory_total: parse_mib(fields[3]),
+                temperature: parse_f64(fields[4]),
+            })
+        })
+        .collect()
+}
+
+fn nvidia_smi() -> Option<String> {
+    let mut cmd = Command::new("nvidia-smi");
+    cmd.arg(NVIDIA_SMI_QUERY)
+        .arg("--format=csv,noheader,nounits");
+    let output = match output_with_timeout(cmd, NVIDIA_SMI_TIMEOUT) {
+        Ok(output) => output,
+        Err(e) => {
+            trace!("nvidia-smi {e}");
+            return None;
+        }
+    };
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// `None` when nvidia-smi is missing, failed or lists no GPU
+fn nvidia_smi_details() -> Option<Vec<GpuDetail>> {
+    Some(parse_nvidia_smi(&nvidia_smi()?)).filter(|details| !details.is_empty())
+}
+
+/// sysfs metrics for every card, except NVIDIA cards when nvidia-smi reported
+/// them with proper metrics
+fn gpu_details(cards: &[DrmCard], nvidia: Option<Vec<GpuDetail>>) -> Vec<GpuDetail> {
+    let mut details: Vec<GpuDetail> = cards
+        .iter()
+        .filter(|card| nvidia.is_none() || card.vendor_id != Some(NVIDIA_VENDOR_ID))
+        .map(sysfs_gpu_detail)
+        .collect();
+    details.extend(nvidia.unwrap_or_default());
+    details
+}
+
+/// GPU names for `BasicInfo`, deduplicated like the CPU brand
+fn gpu_names(details: Vec<GpuDetail>) -> String {
+    let mut names = Vec::new();
+    for detail in details {
+        if !names.contains(&detail.name) {
+            names.push(detail.name);
+        }
+    }
+    let name = names.join(", ");
+    trace!("GPU NAME successfully retrieved: {name}");
+    name
+}
+
+/// GPU names from sysfs only, for when `gpu_metrics` is disabled; never runs
+/// nvidia-smi, so NVIDIA cards get their pci.ids name
+#[must_use]
+pub fn gpu_name() -> String {
+    gpu_names(gpu_details(&drm_cards(Path::new(DRM_ROOT)), None))
+}
+
+type NvidiaResults = Mutex<Option<Vec<GpuDetail>>>;
+
+/// Re-runs nvidia-smi every `interval` so a slow or hung driver never holds up
+/// a realtime sample; the thread exits once the collector is dropped
+fn spawn_nvidia_smi_runner(interval: Duration, results: Weak<NvidiaResults>) {
+    thread::spawn(move || {
+        let mut failing = false;
+        loop {
+            thread::sleep(interval);
+            let details = nvidia_smi_details();
+            if details.is_none() && !failing {
+                warn!("nvidia-smi stopped reporting GPUs, falling back to sysfs");
+            }
+            failing = details.is_none();
+
+            let Some(results) = results.upgrade() else {
+                return;
+            };
+            if let Ok(mut results) = results.lock() {
+                *results = details;
+            }
+        }
+    });
+}
+
+pub struct GpuCollector {
+    cards: Vec<DrmCard>,
+    /// Latest nvidia-smi output, refreshed in the background. `None` when
+    /// nvidia-smi is disabled or found no GPU at startup.
+    nvidia: Option<Arc<NvidiaResults>>,
+}
+
+impl GpuCollector {
+    /// `nvidia_smi_interval` of `None` never runs nvidia-smi
+    #[must_use]
+    pub fn new(nvidia_smi_interval: Option<Duration>) -> Self {
+        Self::with_drm_root(Path::new(DRM_ROOT), nvidia_smi_interval)
+    }
+
+    #[must_use]
+    pub fn with_drm_root(drm_root: &Path, nvidia_smi_interval: Option<Duration>) -> Self {
+        let cards = drm_cards(drm_root);
+        let nvidia = nvidia_smi_interval.and_then(|interval| {
+            let results = Arc::new(Mutex::new(Some(nvidia_smi_details()?)));
+            spawn_nvidia_smi_runner(interval, Arc::downgrade(&results));
+            Some(results)
+        });
+        Self { cards, nvidia }
+    }
+
+    /// GPU names for `BasicInfo`, using the last nvidia-smi result instead of
+    /// running it again
+    #[must_use]
+    pub fn name(&self) -> String {
+        gpu_names(gpu_details(&self.cards, self.nvidia_details()))
+    }
+
+    fn nvidia_details(&self) -> Option<Vec<GpuDetail>> {
+        self.nvidia
+            .as_ref()
+            .and_then(|results| results.lock().ok()?.clone())
+    }
+
+    /// `None` when `gpu_metrics` is disabled
+    #[must_use]
+    pub fn from_config(config: &UserConfig) -> Option<Self> {
+        config.gpu_metrics.then(|| {
+            Self::new(
+                (config.nvidia_smi_interval > 0)
+                    .then(|| Duration::from_secs(config.nvidia_smi_interval)),
+            )
+        })
+    }
+}
+
+impl Default for GpuCollector {
+    fn default() -> Self {
+        Self::new(Some(Duration::from_secs(DEFAULT_NVIDIA_SMI_INTERVAL)))
+    }
+}
+
+impl Collector for GpuCollector {
+    type Output = Option<Gpu>;
+
+    fn collect(&mut self) -> Option<Gpu> {
+        let detailed_info = gpu_details(&self.cards, self.nvidia_details());
+
+        if detailed_info.is_empty() {
+            return None;
+        }
+
+        let average_usage =
+            detailed_info.iter().map(|d| d.utilization).sum::<f64>() / detailed_info.len() as f64;
+        let gpu = Gpu {
+            count: detailed_info.len() as u64,
+            average_usage,
+            detailed_info,
+        };
+        trace!("REALTIME GPU successfully retrieved: {gpu:?}");
+        Some(gpu)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// `card0` and its render node `card1` share an amdgpu device, `card2` is an
+    /// ARM system-on-chip GPU with only a driver link, `card0-DP-1` is a connector
+    fn drm_fixture() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let drm = root.path().join("drm");
+        let amd = root.path().join("devices/pci0000:00/0000:03:00.0");
+        write(&amd.join("vendor"), "0x1002\n");
+        write(&amd.join("device"), "0x73bf\n");
+        write(&amd.join("gpu_busy_percent"), "37\n");
+        write(&amd.join("mem_info_vram_used"), "1073741824\n");
+        write(&amd.join("mem_info_vram_total"), "17163091968\n");
+        write(&amd.join("hwmon/hwmon4/temp1_input"), "54000\n");
+
+        let soc = root.path().join("devices/platform/fde60000.gpu");
+        fs::create_dir_all(&soc).unwrap();
+        let driver = root.path().join("bus/platform/drivers/panfrost");
+        fs::create_dir_all(&driver).unwrap();
+        symlink(&driver, soc.join("driver")).unwrap();
+
+        for (card, device) in [("card0", &amd), ("card1", &amd), ("card2", &soc)] {
+            fs::create_dir_all(drm.join(card)).unwrap();
+            symlink(device, drm.join(card).join("device")).unwrap();
+        }
+        fs::create_dir_all(drm.join("card0-DP-1")).unwrap();
+        root
+    }
+
+    #[test]
+    fn drm_cards_skips_connectors_and_duplicate_render_nodes() {
+        let root = drm_fixture();
+        let cards = drm_cards(&root.path().join("drm"));
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].vendor_id, Some(0x1002));
+        assert!(cards[0].name.starts_with("AMD "), "{}", cards[0].name);
+        assert!(cards[0].device_path.ends_with("card0/device"));
+        assert_eq!(cards[1].vendor_id, None);
+        assert_eq!(cards[1].name, "panfrost");
+    }
+
+    #[test]
+    fn sysfs_gpu_detail_reads_amdgpu_metrics() {
+        let root = drm_fixture();
+        let cards = drm_cards(&root.path().join("drm"));
+
+        let amd = sysfs_gpu_detail(&cards[0]);
+        assert!((amd.utilization - 37.0).abs() < f64::EPSILON);
+        assert_eq!(amd.memory_used, 1_073_741_824);
+        assert_eq!(amd.memory_total, 17_163_091_968);
+        assert!((amd.temperature - 54.0).abs() < f64::EPSILON);
+
+        // Missing files read as zero rather than dropping the card
+        let soc = sysfs_gpu_detail(&cards[1]);
+        assert_eq!(soc.name, "panfrost");
+        assert_eq!((soc.memory_used, soc.memory_total), (0, 0));
+        assert!(soc.utilization.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn collector_without_nvidia_smi_reports_sysfs_cards() {
+        let root = drm_fixture();
+        let gpu = GpuCollector::with_drm_root(&root.path().join("drm"), None)
+            .collect()
+            .unwrap();
+        assert_eq!(gpu.count, 2);
+        assert!((gpu.average_usage - 18.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn parse_nvidia_smi_converts_mib_and_skips_malformed_lines() {
+        let details = parse_nvidia_smi(
+            "NVIDIA GeForce RTX 4090, 12, 2048, 24564, 41\n\
+             Tesla T4, [N/A], 0, 15360, 35\n\
+             garbage line\n",
+        );
+        assert_eq!(details.len(), 2);
+        assert_eq!(details[0].name, "NVIDIA GeForce RTX 4090");
+        assert!((details[0].utilization - 12.0).abs() < f64::EPSILON);
+        assert_eq!(details[0].memory_used, 2048 * 1024 * 1024);
+        assert_eq!(details[0].memory_total, 24564 * 1024 * 1024);
+        assert!((details[0].temperature - 41.0).abs() < f64::EPSILON);
+        // Unsupported fields come back as [N/A]
+        assert!(details[1].utilization.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn gpu_details_prefers_nvidia_smi_for_nvidia_cards() {
+        let card = |vendor_id| DrmCard {
+            name: format!("{vendor_id:04x}"),
+            vendor_id: Some(vendor_id),
+            device_path: PathBuf::from("/nonexistent"),
+        };
+        let cards = [card(0x8086), card(NVIDIA_VENDOR_ID)];
+        let nvidia = parse_nvidia_smi("NVIDIA A100, 90, 1, 2, 60\n");
+
+        let names = |details: Vec<GpuDetail>| -> Vec<String> {
+            details.into_iter().map(|detail| detail.name).collect()
+        };
+        assert_eq!(
+            names(gpu_details(&cards, Some(nvidia))),
+            ["8086", "NVIDIA A100"]
+        );
+        assert_eq!(names(gpu_details(&cards, None)), ["8086", "10de"]);
+    }
+
+    #[test]
+    fn pci_ids_lookup_finds_vendor_and_device() {
+        let pci_ids = "# comment\n1002  Advanced Micro Devices, Inc. [AMD/ATI]\n\t73bf  Navi 21 [Radeon RX 6800/6800 XT / 6900 XT]\n\t\t1002 0e3a  Radeon RX 6900 XT\n10de  NVIDIA Corporation\n\t2684  AD102 [GeForce RTX 4090]\n";
+        assert_eq!(
+            pci_ids_lookup(pci_ids, 0x1002, 0x73bf),
+            Some((
+                "Advanced Micro Devices, Inc. [AMD/ATI]".to_string(),
+                Some("Navi 21 [Radeon RX 6800/6800 XT / 6900 XT]".to_string())
+            ))
+        );
+        // A device of another vendor is not picked up
+        assert_eq!(
+            pci_ids_lookup(pci_ids, 0x1002, 0x2684),
+            Some(("Advanced Micro Devices, Inc. [AMD/ATI]".to_string(), None))
+        );
+        assert_eq!(pci_ids_lookup(pci_ids, 0x8086, 0x1234), None);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::data_struct::{BlockDevice, HardwareInventory, NetworkInterface};
+use crate::get_info::network::interfaces::{SYS_CLASS_NET, read_sysfs_interface};
+use crate::get_info::network::is_filtered_interface;
+use log::trace;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use sysinfo::Networks;
+
+const SYS_BLOCK: &str = "/sys/block";
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// Unit serial number from a SCSI VPD page 0x80, which follows a 4 byte header
+fn parse_vpd_pg80(page: &[u8]) -> Option<String> {
+    let length = usize::from(*page.get(3)?);
+    let serial = String::from_utf8_lossy(page.get(4..4 + length)?)
+        .trim()
+        .to_string();
+    (!serial.is_empty()).then_some(serial)
+}
+
+/// Physical block devices in `sys_block`; loop, ram, zram and device-mapper
+/// devices live under `devices/virtual` and are skipped
+#[must_use]
+pub fn block_devices_with_root(sys_block: &Path) -> Vec<BlockDevice> {
+    let Ok(entries) = fs::read_dir(sys_block) else {
+        return Vec::new();
+    };
+    let mut devices: Vec<BlockDevice> = entries
+        .flatten()
+        .filter(|entry| {
+            fs::read_link(entry.path())
+                .is_ok_and(|target| !target.to_string_lossy().contains("/virtual/"))
+        })
+        .filter_map(|entry| {
+            let dir = entry.path();
+            let name = entry.file_name().to_str()?.to_string();
+            let device = dir.join("device");
+
+            // virtio vendors are PCI ids such as 0x1af4, which say nothing
+            let model = read_trimmed(&device.join("model"))
+                .or_else(|| read_trimmed(&device.join("vendor")).filter(|v| !v.starts_with("0x")))
+                .unwrap_or_default();
+            let serial = read_trimmed(&dir.join("serial"))
+                .or_else(|| read_trimmed(&device.join("serial")))
+                .or_else(|| parse_vpd_pg80(&fs::read(device.join("vpd_pg80")).ok()?))
+                .unwrap_or_default();
+            // Always counted in 512 byte sectors, whatever the logical block size
+            let size = read_trimmed(&dir.join("size"))?.parse::<u64>().ok()? * 512;
+
+            Some(BlockDevice {
+                name,
+                model,
+                serial,
+                size,
+                rotational: read_trimmed(&dir.join("queue/rotational")).as_deref() == Some("1"),
+                removable: read_trimmed(&dir.join("removable")).as_deref() == Some("1"),
+            })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+/// Interfaces counted in the traffic totals, with their addresses
+#[must_use]
+pub fn network_interfaces(networks: &Networks, sys_class_net: &Path) -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = networks
+        .iter()
+        .filter(|(name, data)| !is_filtered_interface(name, data))
+        .map(|(name, data)| {
+            let mut ipv4 = Vec::new();
+            let mut ipv6 = Vec::new();
+            for network in data.ip_networks() {
+                let address = format!("{}/{}", network.addr, network.prefix);
+                match network.addr {
+                    IpAddr::V4(_) => ipv4.push(address),
+                    IpAddr::V6(_) => ipv6.push(address),
+                }
+            }
+            ipv4.sort();
+            ipv6.sort();
+
+            let driver = fs::read_link(sys_class_net.join(name).join("device/driver"))
+                .ok()
+                .and_then(|driver| Some(driver.file_name()?.to_string_lossy().to_string()))
+                .unwrap_or_default();
+
+            NetworkInterface {
+                name: name.clone(),
+                mac: data.mac_address().to_string(),
+                driver,
+                speed: read_sysfs_interface(sys_class_net, name).speed,
+                ipv4,
+                ipv6,
+            }
+        })
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+#[must_use]
+pub fn hardware_inventory() -> HardwareInventory {
+    let inventory = HardwareInventory {
+        block_devices: if cfg!(target_os = "linux") {
+            block_devices_with_root(Path::new(SYS_BLOCK))
+        } else {
+            Vec::new()
+        },
+        network_interfaces: network_interfaces(
+            &Networks::new_with_refreshed_list(),
+            Path::new(SYS_CLASS_NET),
+        ),
+    };
+    trace!("HARDWARE INVENTORY successfully retrieved: {inventory:?}");
+    inventory
+}
+
+/// Takes a fresh hardware inventory every `interval` to catch hotplug
+pub struct HardwareWatcher {
+    interval: Duration,
+    last_check: Instant,
+}
+
+impl HardwareWatcher {
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// `None` until `interval` has passed since the previous inventory
+    pub fn poll(&mut self) -> Option<HardwareInventory> {
+        if self.last_check.elapsed() < self.interval {
+            return None;
+        }
+        self.last_check = Instant::now();
+        Some(hardware_inventory())
+    }
+}
@@ -1,26 +1,111 @@
-use crate::config::IpProvider;
-use log::trace;
+use crate::config::{IpProvider, UserConfig};
+use crate::get_info::stun::stun_query;
+use futures::FutureExt;
+use log::{debug, trace};
+use miniserde::json::Value;
 use miniserde::{Deserialize, Serialize, json};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
+use sysinfo::Networks;
 use tokio::task::JoinHandle;
 
-#[cfg(feature = "ureq-support")]
-use std::time::Duration;
+/// The configured providers and what the `custom` and `local` ones need
+#[derive(Debug, Clone)]
+pub struct IpDetector {
+    pub providers: Vec<IpProvider>,
+    pub custom_url_v4: Option<String>,
+    pub custom_url_v6: Option<String>,
+    /// Dot separated path to the address in a JSON response; `None` = plain text
+    pub custom_json_path: Option<String>,
+    pub local_interface: Option<String>,
+    /// Tried in order by the `stun` provider
+    pub stun_servers: Vec<String>,
+}
 
-pub async fn ip(provider: &IpProvider) -> IPInfo {
-    match provider {
-        IpProvider::Cloudflare => ip_cloudflare().await,
-        IpProvider::Ipinfo => ip_ipinfo().await,
+impl IpDetector {
+    #[must_use]
+    pub fn from_config(config: &UserConfig) -> Self {
+        Self {
+            providers: config.ip_providers.clone(),
+            custom_url_v4: config.ip_custom_url_v4.clone(),
+            custom_url_v6: config.ip_custom_url_v6.clone(),
+            custom_json_path: config.ip_custom_json_path.clone(),
+            local_interface: config.ip_local_interface.clone(),
+            stun_servers: config.ip_stun_servers.clone(),
+        }
     }
 }
 
-#[derive(Debug)]
+/// Try each provider in order until both address families are known
+pub async fn ip(detector: &IpDetector) -> IPInfo {
+    let mut ip_info = IPInfo {
+        ipv4: None,
+        ipv6: None,
+    };
+    for provider in &detector.providers {
+        let found = match provider {
+            IpProvider::Cloudflare => ip_cloudflare().await,
+            IpProvider::Ipinfo => ip_ipinfo().await,
+            IpProvider::Custom => ip_custom(detector).await,
+            IpProvider::Stun => ip_stun(&detector.stun_servers).await,
+            IpProvider::Local => ip_local(detector.local_interface.as_deref().unwrap_or_default()),
+        };
+        ip_info.ipv4 = ip_info.ipv4.or(found.ipv4);
+        ip_info.ipv6 = ip_info.ipv6.or(found.ipv6);
+        if ip_info.ipv4.is_some() && ip_info.ipv6.is_some() {
+            break;
+        }
+    }
+    ip_info
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct IPInfo {
     pub ipv4: Option<Ipv4Addr>,
     pub ipv6: Option<Ipv6Addr>,
 }
 
+/// Re-runs public IP detection in the background every `interval`
+pub struct IpWatcher {
+    detector: IpDetector,
+    interval: Duration,
+    last_check: Instant,
+    pending: Option<JoinHandle<IPInfo>>,
+}
+
+impl IpWatcher {
+    #[must_use]
+    pub fn new(detector: IpDetector, interval: Duration) -> Self {
+        Self {
+            detector,
+            interval,
+            last_check: Instant::now(),
+            pending: None,
+        }
+    }
+
+    /// Never blocks: starts a detection once `interval` has passed and returns
+    /// its result on the first call after it finished
+    pub fn poll(&mut self) -> Option<IPInfo> {
+        if let Some(pending) = &self.pending {
+            if !pending.is_finished() {
+                return None;
+            }
+            let result = self.pending.take()?.now_or_never()?.ok();
+            self.last_check = Instant::now();
+            debug!("Public IP re-detected: {result:?}");
+            return result;
+        }
+
+        if self.last_check.elapsed() >= self.interval {
+            let detector = self.detector.clone();
+            self.pending = Some(tokio::spawn(async move { ip(&detector).await }));
+        }
+        None
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct IpJson {
     ip: String,
@@ -30,7 +115,9 @@ struct IpJson {
 async fn fetch_ipinfo_v4() -> Option<String> {
     #[cfg(feature = "ureq-support")]
     {
-        let resp = ureq::get("https://ipinfo.io")
+        let url = "https://ipinfo.io";
+        let resp = crate::utils::create_ureq_agent(url, false)
+            .get(url)
             .header("User-Agent", "curl/8.7.1")
             .config()
             .timeout_global(Some(Duration::from_secs(5)))
@@ -60,7 +147,9 @@ async fn fetch_ipinfo_v4() -> Option<String> {
 async fn fetch_ipinfo_v6() -> Option<String> {
     #[cfg(feature = "ureq-support")]
     {
-        let resp = ureq::get("https://6.ipinfo.io")
+        let url = "https://6.ipinfo.io";
+        let resp = crate::utils::create_ureq_agent(url, false)
+            .get(url)
             .header("User-Agent", "curl/8.7.1")
             .config()
             .timeout_global(Some(Duration::from_secs(5)))
@@ -90,7 +179,9 @@ async fn fetch_ipinfo_v6() -> Option<String> {
 async fn fetch_cloudflare_v4() -> Option<String> {
     #[cfg(feature = "ureq-support")]
     {
-        let resp = ureq::get("https://www.cloudflare.com/cdn-cgi/trace")
+        let url = "https://www.cloudflare.com/cdn-cgi/trace";
+        let resp = crate::utils::create_ureq_agent(url, false)
+            .get(url)
             .header("User-Agent", "curl/8.7.1")
             .config()
             .timeout_global(Some(Duration::from_secs(5)))
@@ -120,7 +211,9 @@ async fn fetch_cloudflare_v4() -> Option<String> {
 async fn fetch_cloudflare_v6() -> Option<String> {
     #[cfg(feature = "ureq-support")]
     {
-        let resp = ureq::get("https://www.cloudflare.com/cdn-cgi/trace")
+        let url = "https://www.cloudflare.com/cdn-cgi/trace";
+        let resp = crate::utils::create_ureq_agent(url, false)
+            .get(url)
             .header("User-Agent", "curl/8.7.1")
             .config()
             .timeout_global(Some(Duration::from_secs(5)))
@@ -224,3 +317,277 @@ pub async fn ip_cloudflare() -> IPInfo {
 
     ip_info
 }
+
+async fn fetch_url(url: &str, ipv6: bool) -> Option<String> {
+    #[cfg(not(any(feature = "ureq-support", feature = "nyquest-support")))]
+    let _ = (url, ipv6);
+
+    #[cfg(feature = "ureq-support")]
+    {
+        let family = if ipv6 {
+            ureq::config::IpFamily::Ipv6Only
+        } else {
+            ureq::config::IpFamily::Ipv4Only
+        };
+        let resp = crate::utils::create_ureq_agent(url, false)
+            .get(url)
+            .header("User-Agent", "curl/8.7.1")
+            .config()
+            .timeout_global(Some(Duration::from_secs(5)))
+            .ip_family(family)
+            .build()
+            .call();
+
+        if let Ok(mut response) = resp {
+            return response.body_mut().read_to_string().ok();
+        }
+    }
+
+    #[cfg(feature = "nyquest-support")]
+    {
+        // nyquest cannot pin the address family; the parsed address is checked instead
+        let _ = ipv6;
+        use nyquest::Request;
+        let client = crate::utils::create_nyquest_client(false);
+        let request = Request::get(url.to_string());
+
+        if let Ok(res) = client.request(request) {
+            return res.text().ok();
+        }
+    }
+
+    None
+}
+
+/// Follow a dot separated path such as `data.ip` or `addresses.0`
+#[must_use]
+pub fn json_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            Value::Object(object) => object.get(key),
+            Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// The address in a custom provider's response body
+#[must_use]
+pub fn parse_custom_response(body: &str, json_path_expr: Option<&str>) -> Option<IpAddr> {
+    let text = match json_path_expr {
+        None => body.trim().to_string(),
+        Some(path) => {
+            let value: Value = json::from_str(body).ok()?;
+            match json_path(&value, path)? {
+                Value::String(text) => text.clone(),
+                _ => return None,
+            }
+        }
+    };
+    IpAddr::from_str(text.trim()).ok()
+}
+
+pub async fn ip_custom(detector: &IpDetector) -> IPInfo {
+    let fetch = |url: Option<String>, ipv6: bool| {
+        let json_path_expr = detector.custom_json_path.clone();
+        tokio::spawn(async move {
+            let body = fetch_url(&url?, ipv6).await?;
+            parse_custom_response(&body, json_path_expr.as_deref())
+        })
+    };
+    let ipv4 = fetch(detector.custom_url_v4.clone(), false);
+    let ipv6 = fetch(detector.custom_url_v6.clone(), true);
+
+    let ip_info = IPInfo {
+        ipv4: match ipv4.await.unwrap_or(None) {
+            Some(IpAddr::V4(ip)) => Some(ip),
+            _ => None,
+        },
+        ipv6: match ipv6.await.unwrap_or(None) {
+            Some(IpAddr::V6(ip)) => Some(ip),
+            _ => None,
+        },
+    };
+
+    trace!("IP INFO (custom) successfully retrieved: {ip_info:?}");
+
+    ip_info
+}
+
+/// IPv6 addresses in `fc00::/7` (unique local) or `fe80::/10` (link local)
+fn is_private_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+}
+
+/// Addresses assigned to `interface`. Loopback and link-local addresses are
+/// skipped, and a global IPv6 address is preferred over a unique local one.
+#[must_use]
+pub fn ip_local(interface: &str) -> IPInfo {
+    let networks = Networks::new_with_refreshed_list();
+    let addresses: Vec<IpAddr> = networks
+        .get(interface)
+        .map(|data| {
+            data.ip_networks()
+                .iter()
+                .map(|network| network.addr)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let ipv4 = addresses.iter().find_map(|ip| match ip {
+        IpAddr::V4(ip) if !ip.is_loopback() && !ip.is_link_local() => Some(*ip),
+        _ => None,
+    });
+    let ipv6_candidates: Vec<Ipv6Addr> = addresses
+        .iter()
+        .filter_map(|ip| match ip {
+            IpAddr::V6(ip) if !ip.is_loopback() && ip.segments()[0] & 0xffc0 != 0xfe80 => Some(*ip),
+            _ => None,
+        })
+        .collect();
+    let ipv6 = ipv6_candidates
+        .iter()
+        .find(|ip| !is_private_v6(ip))
+        .or_else(|| ipv6_candidates.first())
+        .copied();
+
+    let ip_info = IPInfo { ipv4, ipv6 };
+
+    trace!("IP INFO (local {interface}) successfully retrieved: {ip_info:?}");
+
+    ip_info
+}
+
+pub async fn ip_stun(servers: &[String]) -> IPInfo {
+    let query = |ipv6: bool| {
+        let servers = servers.to_vec();
+        tokio::spawn(async move {
+            for server in &servers {
+                if let Some(ip) = stun_query(server, ipv6).await {
+                    return Some(ip);
+                }
+            }
+            None
+        })
+    };
+    let ipv4 = query(false);
+    let ipv6 = query(true);
+
+    let ip_info = IPInfo {
+        ipv4: match ipv4.await.unwrap_or(None) {
+            Some(IpAddr::V4(ip)) => Some(ip),
+            _ => None,
+        },
+        ipv6: match ipv6.await.unwrap_or(None) {
+            Some(IpAddr::V6(ip)) => Some(ip),
+            _ => None,
+        },
+    };
+
+    trace!("IP INFO (stun) successfully retrieved: {ip_info:?}");
+
+    ip_info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_path_walks_objects_and_arrays() {
+        let value: Value =
+            json::from_str(r#"{"data":{"addresses":["198.51.100.1","2001:db8::1"]},"ip":"x"}"#)
+                .unwrap();
+        let text = |path| match json_path(&value, path) {
+            Some(Value::String(text)) => Some(text.as_str()),
+            _ => None,
+        };
+        assert_eq!(text("data.addresses.1"), Some("2001:db8::1"));
+        assert_eq!(text(".ip."), Some("x"));
+        assert_eq!(text("data.addresses.2"), None);
+        assert_eq!(text("data.missing"), None);
+        assert_eq!(text("ip.0"), None);
+    }
+
+    #[test]
+    fn parse_custom_response_reads_text_or_json() {
+        assert_eq!(
+            parse_custom_response(" 203.0.113.7\n", None),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+        assert_eq!(
+            parse_custom_response(r#"{"result":{"ip":"2001:db8::7"}}"#, Some("result.ip")),
+            "2001:db8::7".parse().ok()
+        );
+        // Only strings are addresses
+        assert_eq!(parse_custom_response(r#"{"ip":42}"#, Some("ip")), None);
+        assert_eq!(parse_custom_response("<html>", None), None);
+        assert_eq!(parse_custom_response("203.0.113.7", Some("ip")), None);
+    }
+
+    /// A one-route HTTP server on localhost answering every request with `body`
+    #[cfg(feature = "ureq-support")]
+    fn serve(body: &'static str) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ip", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    line.clear();
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "ureq-support")]
+    #[tokio::test]
+    async fn ip_custom_fetches_and_parses_each_family() {
+        let detector = IpDetector {
+            providers: vec![IpProvider::Custom],
+            custom_url_v4: Some(serve(r#"{"data":{"ip":"203.0.113.7"}}"#)),
+            custom_url_v6: None,
+            custom_json_path: Some("data.ip".to_string()),
+            local_interface: None,
+            stun_servers: Vec::new(),
+        };
+        assert_eq!(
+            ip_custom(&detector).await,
+            IPInfo {
+                ipv4: Some(Ipv4Addr::new(203, 0, 113, 7)),
+                ipv6: None,
+            }
+        );
+    }
+
+    #[cfg(feature = "ureq-support")]
+    #[tokio::test]
+    async fn ip_custom_drops_an_address_of_the_wrong_family() {
+        let detector = IpDetector {
+            providers: vec![IpProvider::Custom],
+            custom_url_v4: Some(serve("2001:db8::7\n")),
+            custom_url_v6: None,
+            custom_json_path: None,
+            local_interface: None,
+            stun_servers: Vec::new(),
+        };
+        assert_eq!(
+            ip_custom(&detector).await,
+            IPInfo {
+                ipv4: None,
+                ipv6: None,
+            }
+        );
+    }
+}
@@ -1,7 +1,20 @@
 use crate::data_struct::Load;
+use crate::get_info::Collector;
 use log::trace;
 
+#[derive(Debug, Default)]
+pub struct LoadCollector;
+
+impl Collector for LoadCollector {
+    type Output = Load;
+
+    fn collect(&mut self) -> Load {
+        realtime_load()
+    }
+}
+
 #[cfg(not(target_os = "windows"))]
+#[must_use]
 pub fn realtime_load() -> Load {
     use sysinfo::System;
     let load = System::load_average();
@@ -1,8 +1,14 @@
-use crate::data_struct::{Disk, Ram, Swap};
+use crate::config::{MemUsedMode, UserConfig};
+use crate::data_struct::{Disk, DiskIo, DiskMount, Ram, Swap};
+use crate::get_info::Collector;
+use crate::get_info::disk_io::{DiskStats, disk_io_rates, diskstats_device_name, read_diskstats};
 use log::trace;
-use std::collections::HashSet;
-use std::sync::OnceLock;
-use sysinfo::{Disks, System};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::BuildHasher;
+use std::path::Path;
+use std::time::Instant;
+use sysinfo::{DiskRefreshKind, Disks, MemoryRefreshKind, System};
 
 #[derive(Debug)]
 pub struct MemDiskTotalInfoWithOutUsage {
@@ -11,12 +17,16 @@ pub struct MemDiskTotalInfoWithOutUsage {
     pub disk: u64,
 }
 
-pub fn mem_info_without_usage(sysinfo_sys: &System) -> MemDiskTotalInfoWithOutUsage {
+#[must_use]
+pub fn mem_info_without_usage(
+    sysinfo_sys: &System,
+    disk_filter: &DiskFilter,
+) -> MemDiskTotalInfoWithOutUsage {
     let mem_total = sysinfo_sys.total_memory();
     let swap_total = sysinfo_sys.total_swap();
 
     let disks = Disks::new_with_refreshed_list();
-    let disk_list = filter_disks(&disks);
+    let disk_list = filter_disks(&disks, disk_filter);
     let mut all_disk_space: u64 = 0;
     for disk in &disk_list {
         all_disk_space += disk.total_space();
@@ -33,14 +43,97 @@ pub fn mem_info_without_usage(sysinfo_sys: &System) -> MemDiskTotalInfoWithOutUs
     info
 }
 
-pub fn realtime_mem(sysinfo_sys: &System) -> Ram {
+/// Parse /proc/meminfo into bytes keyed by field name
+#[must_use]
+pub fn parse_meminfo(content: &str) -> HashMap<String, u64> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let mut parts = value.split_whitespace();
+            let value = parts.next()?.parse::<u64>().ok()?;
+            let value = if parts.next() == Some("kB") {
+                value * 1024
+            } else {
+                value
+            };
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// Current ARC size in bytes from the content of /proc/spl/kstat/zfs/arcstats
+#[must_use]
+pub fn parse_arcstats(content: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        // name type data
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["size", _, data] => data.parse::<u64>().ok(),
+            _ => None,
+        }
+    })
+}
+
+#[must_use]
+pub fn zfs_arc_size() -> Option<u64> {
+    parse_arcstats(&fs::read_to_string("/proc/spl/kstat/zfs/arcstats").ok()?)
+}
+
+/// Used memory in bytes under `used_mode`. `available` is sysinfo's figure,
+/// which it estimates itself on kernels without `MemAvailable`; `meminfo` is
+/// empty where there is no /proc/meminfo.
+#[must_use]
+pub fn mem_used<S: BuildHasher>(
+    used_mode: &MemUsedMode,
+    total: u64,
+    available: u64,
+    meminfo: &HashMap<String, u64, S>,
+    arc: u64,
+) -> u64 {
+    let field = |key: &str| meminfo.get(key).copied().unwrap_or(0);
+    match used_mode {
+        MemUsedMode::ExcludeArc => total.saturating_sub(available).saturating_sub(arc),
+        // htop's formula: shared memory (tmpfs, SysV shm) sits in Cached but
+        // cannot be dropped, so it stays used
+        MemUsedMode::ExcludeCache if !meminfo.is_empty() => total.saturating_sub(
+            field("MemFree")
+                + field("Buffers")
+                + (field("Cached") + field("SReclaimable")).saturating_sub(field("Shmem"))
+                + arc,
+        ),
+        MemUsedMode::Available | MemUsedMode::ExcludeCache => total.saturating_sub(available),
+    }
+}
+
+#[must_use]
+pub fn realtime_mem(sysinfo_sys: &System, used_mode: &MemUsedMode) -> Ram {
+    let total = sysinfo_sys.total_memory();
+    let available = sysinfo_sys.available_memory();
+
+    // Only Linux has /proc/meminfo; elsewhere the breakdown stays at 0
+    let meminfo = fs::read_to_string("/proc/meminfo")
+        .map(|content| parse_meminfo(&content))
+        .unwrap_or_default();
+    let field = |key: &str| meminfo.get(key).copied().unwrap_or(0);
+    let zfs_arc = zfs_arc_size();
+    let used = mem_used(used_mode, total, available, &meminfo, zfs_arc.unwrap_or(0));
+
     let ram = Ram {
-        used: sysinfo_sys.total_memory() - sysinfo_sys.available_memory(),
+        used,
+        available,
+        cached: field("Cached"),
+        buffers: field("Buffers"),
+        shared: field("Shmem"),
+        dirty: field("Dirty"),
+        writeback: field("Writeback"),
+        zfs_arc,
     };
     trace!("REALTIME MEM successfully retrieved: {ram:?}");
     ram
 }
 
+#[must_use]
 pub fn realtime_swap(sysinfo_sys: &System) -> Swap {
     let swap = Swap {
         used: sysinfo_sys.used_swap(),
@@ -49,90 +142,297 @@ pub fn realtime_swap(sysinfo_sys: &System) -> Swap {
     swap
 }
 
-pub fn realtime_disk(disk: &Disks) -> Disk {
+#[must_use]
+pub fn realtime_disk(disk: &Disks, disk_filter: &DiskFilter) -> Disk {
     let mut used_disk: u64 = 0;
-    let disk_list = filter_disks(disk);
+    let mut mounts = Vec::new();
+    let disk_list = filter_disks(disk, disk_filter);
     for disk in disk_list {
         trace!("FILTERED DISK: {disk:?}");
-        used_disk += disk.total_space() - disk.available_space();
+        let used = disk.total_space() - disk.available_space();
+        used_disk += used;
+
+        let (inodes_used, inodes_total) = mount_inodes(disk.mount_point());
+        mounts.push(DiskMount {
+            mount_point: disk.mount_point().to_string_lossy().into_owned(),
+            fs_type: disk.file_system().to_string_lossy().into_owned(),
+            used,
+            total: disk.total_space(),
+            inodes_used,
+            inodes_total,
+        });
     }
 
-    let disk_info = Disk { used: used_disk };
+    let disk_info = Disk {
+        used: used_disk,
+        io: DiskIo::default(),
+        devices: Vec::new(),
+        mounts,
+    };
     trace!("REALTIME DISK successfully retrieved: {disk_info:?}");
     disk_info
 }
 
-fn get_allowed_filesystems() -> &'static HashSet<&'static str> {
-    static ALLOWED_FS: OnceLock<HashSet<&str>> = OnceLock::new();
-    ALLOWED_FS.get_or_init(|| {
-        [
-            "apfs",
-            "ext4",
-            "ext3",
-            "ext2",
-            "f2fs",
-            "reiserfs",
-            "jfs",
-            "btrfs",
-            "fuseblk",
-            "zfs",
-            "simfs",
-            "ntfs",
-            "fat32",
-            "exfat",
-            "xfs",
-            "fuse.rclone",
-            "ubifs",
-        ]
-        .iter()
-        .cloned()
-        .collect()
-    })
+pub struct MemCollector {
+    sysinfo_sys: System,
+    used_mode: MemUsedMode,
 }
 
-fn get_exclude_keywords() -> &'static HashSet<&'static str> {
-    static EXCLUDE_KEYWORDS: OnceLock<HashSet<&str>> = OnceLock::new();
-    EXCLUDE_KEYWORDS.get_or_init(|| {
-        [
-            "/snap",
-            "/var/lib/docker",
-            "/var/lib/lxcfs",
-            "/run/user",
-            "/tmp",
-            "/dev",
-            "/sys",
-            "/proc",
-            "/boot",
-            "/lost+found",
-            "/nix/store",
-            "/var/log.hdd",
-        ]
-        .iter()
-        .cloned()
-        .collect()
-    })
+impl MemCollector {
+    #[must_use]
+    pub fn new(used_mode: MemUsedMode) -> Self {
+        Self {
+            sysinfo_sys: System::new(),
+            used_mode,
+        }
+    }
+}
+
+impl Default for MemCollector {
+    fn default() -> Self {
+        Self::new(MemUsedMode::Available)
+    }
+}
+
+impl Collector for MemCollector {
+    type Output = (Ram, Swap);
+
+    fn collect(&mut self) -> (Ram, Swap) {
+        self.sysinfo_sys
+            .refresh_memory_specifics(MemoryRefreshKind::everything());
+        (
+            realtime_mem(&self.sysinfo_sys, &self.used_mode),
+            realtime_swap(&self.sysinfo_sys),
+        )
+    }
+}
+
+pub struct DiskCollector {
+    disks: Disks,
+    disk_filter: DiskFilter,
+    last_diskstats: HashMap<String, DiskStats>,
+    last_sample: Instant,
+}
+
+impl DiskCollector {
+    #[must_use]
+    pub fn new(disk_filter: DiskFilter) -> Self {
+        Self {
+            disks: Disks::new(),
+            disk_filter,
+            last_diskstats: read_diskstats(),
+            last_sample: Instant::now(),
+        }
+    }
+
+    /// I/O rates of the devices backing the filtered mounts since the previous call
+    fn realtime_disk_io(&mut self) -> (DiskIo, Vec<DiskIo>) {
+        let devices: Vec<String> = filter_disks(&self.disks, &self.disk_filter)
+            .iter()
+            .map(|disk| diskstats_device_name(Path::new(disk.name())))
+            .collect();
+
+        let diskstats = read_diskstats();
+        let elapsed = self.last_sample.elapsed().as_secs_f64();
+        let rates = disk_io_rates(&devices, &self.last_diskstats, &diskstats, elapsed);
+        self.last_diskstats = diskstats;
+        self.last_sample = Instant::now();
+
+        trace!("REALTIME DISK IO successfully retrieved: {rates:?}");
+        rates
+    }
+}
+
+impl Default for DiskCollector {
+    fn default() -> Self {
+        Self::new(DiskFilter::default())
+    }
+}
+
+impl Collector for DiskCollector {
+    type Output = Disk;
+
+    fn collect(&mut self) -> Disk {
+        self.disks
+            .refresh_specifics(true, DiskRefreshKind::nothing().with_storage());
+        let mut disk = realtime_disk(&self.disks, &self.disk_filter);
+        (disk.io, disk.devices) = self.realtime_disk_io();
+        disk
+    }
+}
+
+const DEFAULT_FILESYSTEMS: &[&str] = &[
+    "apfs",
+    "ext4",
+    "ext3",
+    "ext2",
+    "f2fs",
+    "reiserfs",
+    "jfs",
+    "btrfs",
+    "fuseblk",
+    "zfs",
+    "simfs",
+    "ntfs",
+    "fat32",
+    "exfat",
+    "xfs",
+    "fuse.rclone",
+    "ubifs",
+];
+
+const DEFAULT_EXCLUDE_MOUNTS: &[&str] = &[
+    "/snap",
+    "/var/lib/docker",
+    "/var/lib/lxcfs",
+    "/run/user",
+    "/tmp",
+    "/dev",
+    "/sys",
+    "/proc",
+    "/boot",
+    "/lost+found",
+    "/nix/store",
+    "/var/log.hdd",
+];
+
+/// Which mounts count as disks.
+///
+/// A mount is selected when it matches `include_mounts`, or when its
+/// filesystem is a built-in one or in `filesystems` and it matches none of
+/// `exclude_mounts`.
+#[derive(Debug, Clone)]
+pub struct DiskFilter {
+    filesystems: HashSet<String>,
+    include_mounts: Vec<String>,
+    exclude_mounts: Vec<String>,
+}
+
+impl DiskFilter {
+    /// `filesystems` are counted on top of the built-in list; an empty
+    /// `exclude_mounts` falls back to the built-in list
+    #[must_use]
+    pub fn new(
+        filesystems: &[String],
+        include_mounts: &[String],
+        exclude_mounts: &[String],
+    ) -> Self {
+        let filesystems = DEFAULT_FILESYSTEMS
+            .iter()
+            .map(|fs| (*fs).to_string())
+            .chain(filesystems.iter().cloned())
+            .collect();
+        let exclude_mounts = if exclude_mounts.is_empty() {
+            DEFAULT_EXCLUDE_MOUNTS
+                .iter()
+                .map(|m| (*m).to_string())
+                .collect()
+        } else {
+            exclude_mounts.to_vec()
+        };
+
+        Self {
+            filesystems,
+            include_mounts: include_mounts.to_vec(),
+            exclude_mounts,
+        }
+    }
+
+    #[must_use]
+    pub fn from_config(config: &UserConfig) -> Self {
+        Self::new(
+            &config.disk_filesystems,
+            &config.disk_include_mounts,
+            &config.disk_exclude_mounts,
+        )
+    }
+
+    #[must_use]
+    pub fn is_selected(&self, fs: &str, mount_point: &str) -> bool {
+        if self
+            .include_mounts
+            .iter()
+            .any(|pattern| mount_pattern_matches(pattern, mount_point))
+        {
+            return true;
+        }
+
+        self.filesystems.contains(fs)
+            && !self
+                .exclude_mounts
+                .iter()
+                .any(|pattern| mount_pattern_matches(pattern, mount_point))
+    }
 }
 
-pub fn filter_disks(disks: &Disks) -> Vec<&sysinfo::Disk> {
-    let allowed_fs = get_allowed_filesystems();
-    let exclude_keywords = get_exclude_keywords();
+impl Default for DiskFilter {
+    fn default() -> Self {
+        Self::new(&[], &[], &[])
+    }
+}
+
+/// `/tmp` matches `/tmp` and `/tmp/x` but not `/data/tmp` or `/tmpfs`;
+/// patterns containing `*` are matched as globs against the whole mount point
+#[must_use]
+pub fn mount_pattern_matches(pattern: &str, mount_point: &str) -> bool {
+    if pattern.contains('*') {
+        return glob_matches(pattern.as_bytes(), mount_point.as_bytes());
+    }
 
+    let pattern = if pattern.len() > 1 {
+        pattern.trim_end_matches('/')
+    } else {
+        pattern
+    };
+    mount_point == pattern
+        || (mount_point.starts_with(pattern)
+            && (pattern.ends_with('/') || mount_point.as_bytes().get(pattern.len()) == Some(&b'/')))
+}
+
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_matches(rest, &text[i..])),
+        Some((c, rest)) => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
+
+/// (used, total) inodes of the filesystem mounted at `mount_point`
+#[cfg(unix)]
+#[must_use]
+pub fn mount_inodes(mount_point: &Path) -> (u64, u64) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(mount_point.as_os_str().as_bytes()) else {
+        return (0, 0);
+    };
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &raw mut stat) } != 0 {
+        return (0, 0);
+    }
+
+    // fsfilcnt_t is only 32 bits wide on some targets
+    #[allow(clippy::useless_conversion)]
+    let (total, free) = (u64::from(stat.f_files), u64::from(stat.f_ffree));
+    (total.saturating_sub(free), total)
+}
+
+#[cfg(not(unix))]
+pub fn mount_inodes(_mount_point: &Path) -> (u64, u64) {
+    (0, 0)
+}
+
+#[must_use]
+pub fn filter_disks<'a>(disks: &'a Disks, disk_filter: &DiskFilter) -> Vec<&'a sysinfo::Disk> {
     let mut unique_disks = Vec::new();
     let mut seen_devices = HashSet::new();
 
     for disk in disks.iter() {
-        // Filter by filesystem type
+        // Filter by filesystem type and mount point
         let fs = disk.file_system().to_string_lossy();
-        if !allowed_fs.contains(fs.as_ref()) {
-            continue;
-        }
-
-        // Filter by mount point
         let mount_point = disk.mount_point().to_string_lossy();
-        if exclude_keywords
-            .iter()
-            .any(|keyword| mount_point.contains(keyword))
-        {
+        if !disk_filter.is_selected(&fs, &mount_point) {
             continue;
         }
 
@@ -145,3 +445,219 @@ pub fn filter_disks(disks: &Disks) -> Vec<&sysinfo::Disk> {
 
     unique_disks
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    /// 16 GiB machine with 2 GiB of tmpfs and a 4 GiB ZFS ARC that the kernel
+    /// counts as used
+    const MEMINFO: &str = "\
+MemTotal:       16777216 kB
+MemFree:         2097152 kB
+MemAvailable:    6291456 kB
+Buffers:          524288 kB
+Cached:          5242880 kB
+SwapCached:            0 kB
+Shmem:           2097152 kB
+SReclaimable:     524288 kB
+Dirty:              1024 kB
+Writeback:             0 kB
+HugePages_Total:       0
+";
+
+    /// Kernels before 3.14 have no `MemAvailable`
+    const MEMINFO_OLD: &str = "\
+MemTotal:        4194304 kB
+MemFree:         1048576 kB
+Buffers:          262144 kB
+Cached:          1048576 kB
+Shmem:            131072 kB
+SReclaimable:     131072 kB
+";
+
+    const ARCSTATS: &str = "\
+13 1 0x01 123 33456 5373485231 1276123312412
+name                            type data
+hits                            4    6783123
+misses                          4    123123
+c_max                           4    8589934592
+size                            4    4294967296
+compressed_size                 4    1073741824
+";
+
+    #[test]
+    fn parse_meminfo_converts_kilobytes_to_bytes() {
+        let meminfo = parse_meminfo(MEMINFO);
+        assert_eq!(meminfo["MemTotal"], 16 * GIB);
+        assert_eq!(meminfo["Shmem"], 2 * GIB);
+        assert_eq!(meminfo["Dirty"], 1024 * 1024);
+        // Counts without a unit stay as they are
+        assert_eq!(meminfo["HugePages_Total"], 0);
+        assert_eq!(meminfo["MemAvailable"], 6 * GIB);
+    }
+
+    #[test]
+    fn parse_arcstats_reads_the_current_size() {
+        assert_eq!(parse_arcstats(ARCSTATS), Some(4 * GIB));
+        assert_eq!(parse_arcstats("name type data\nhits 4 1\n"), None);
+    }
+
+    #[test]
+    fn mem_used_follows_the_configured_mode() {
+        let meminfo = parse_meminfo(MEMINFO);
+        let (total, available) = (16 * GIB, 6 * GIB);
+
+        assert_eq!(
+            mem_used(&MemUsedMode::Available, total, available, &meminfo, 4 * GIB),
+            10 * GIB
+        );
+        assert_eq!(
+            mem_used(
+                &MemUsedMode::ExcludeArc,
+                total,
+                available,
+                &meminfo,
+                4 * GIB
+            ),
+            6 * GIB
+        );
+        // 16 - (2 free + 0.5 buffers + (5 cached + 0.5 reclaimable - 2 shmem) + 4 ARC)
+        assert_eq!(
+            mem_used(
+                &MemUsedMode::ExcludeCache,
+                total,
+                available,
+                &meminfo,
+                4 * GIB
+            ),
+            6 * GIB
+        );
+        assert_eq!(
+            mem_used(&MemUsedMode::ExcludeCache, total, available, &meminfo, 0),
+            10 * GIB
+        );
+    }
+
+    #[test]
+    fn mem_used_never_underflows() {
+        let meminfo = parse_meminfo(MEMINFO);
+        assert_eq!(
+            mem_used(
+                &MemUsedMode::ExcludeArc,
+                16 * GIB,
+                6 * GIB,
+                &meminfo,
+                12 * GIB
+            ),
+            0
+        );
+        assert_eq!(
+            mem_used(
+                &MemUsedMode::ExcludeCache,
+                16 * GIB,
+                6 * GIB,
+                &meminfo,
+                12 * GIB
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn mem_used_without_mem_available() {
+        let meminfo = parse_meminfo(MEMINFO_OLD);
+        assert!(!meminfo.contains_key("MemAvailable"));
+        // sysinfo estimates available memory itself on these kernels
+        let (total, available) = (4 * GIB, 2 * GIB + GIB / 4);
+
+        assert_eq!(
+            mem_used(&MemUsedMode::Available, total, available, &meminfo, 0),
+            GIB + 3 * GIB / 4
+        );
+        // 4 - (1 free + 0.25 buffers + (1 cached + 0.125 reclaimable - 0.125 shmem))
+        assert_eq!(
+            mem_used(&MemUsedMode::ExcludeCache, total, available, &meminfo, 0),
+            GIB + 3 * GIB / 4
+        );
+        // Without /proc/meminfo the cache breakdown is unknown
+        assert_eq!(
+            mem_used(
+                &MemUsedMode::ExcludeCache,
+                total,
+                available,
+                &HashMap::new(),
+                0
+            ),
+            GIB + 3 * GIB / 4
+        );
+    }
+
+    #[test]
+    fn mount_pattern_matches_whole_path_components() {
+        for (pattern, mount_point, expected) in [
+            ("/tmp", "/tmp", true),
+            ("/tmp", "/tmp/build", true),
+            ("/tmp/", "/tmp/build", true),
+            ("/tmp", "/tmpfoo", false),
+            ("/tmp", "/tmpfs", false),
+            ("/tmp", "/data/tmp", false),
+            ("/", "/", true),
+            ("/", "/home", true),
+            (
+                "/var/lib/docker",
+                "/var/lib/docker/overlay2/abc/merged",
+                true,
+            ),
+            ("/var/lib/docker", "/var/lib", false),
+        ] {
+            assert_eq!(
+                mount_pattern_matches(pattern, mount_point),
+                expected,
+                "{pattern} against {mount_point}"
+            );
+        }
+    }
+
+    #[test]
+    fn mount_pattern_matches_globs_the_whole_mount_point() {
+        for (pattern, mount_point, expected) in [
+            ("/mnt/backup-*", "/mnt/backup-2024", true),
+            ("/mnt/backup-*", "/mnt/backup-", true),
+            ("/mnt/backup-*", "/mnt/backup", false),
+            ("/mnt/backup-*", "/data/mnt/backup-1", false),
+            ("*/tmp", "/data/tmp", true),
+            ("*/tmp", "/data/tmpfoo", false),
+            ("/media/*/usb", "/media/alice/usb", true),
+            ("/media/*/usb", "/media/alice/usb2", false),
+            ("*", "/anything", true),
+        ] {
+            assert_eq!(
+                mount_pattern_matches(pattern, mount_point),
+                expected,
+                "{pattern} against {mount_point}"
+            );
+        }
+    }
+
+    #[test]
+    fn disk_filter_adds_configured_filesystems_to_the_defaults() {
+        let filter = DiskFilter::new(&["nfs4".to_string()], &[], &[]);
+        assert!(filter.is_selected("nfs4", "/mnt/share"));
+        assert!(filter.is_selected("ext4", "/"));
+        assert!(filter.is_selected("xfs", "/data"));
+        assert!(!filter.is_selected("tmpfs", "/run"));
+        assert!(!filter.is_selected("ext4", "/boot"));
+    }
+
+    #[test]
+    fn disk_filter_include_mounts_bypass_the_other_filters() {
+        let filter = DiskFilter::new(&[], &["/boot".to_string()], &["/data".to_string()]);
+        assert!(filter.is_selected("vfat", "/boot/efi"));
+        assert!(!filter.is_selected("ext4", "/data/archive"));
+        // A non-empty exclude list replaces the built-in one
+        assert!(filter.is_selected("ext4", "/tmp"));
+    }
+}
@@ -1,20 +1,57 @@
-use log::trace;
+use crate::config::UserConfig;
+use crate::get_info::cgroup::{Cgroup, CgroupCollector};
+use crate::get_info::cpu::CpuCollector;
+use crate::get_info::custom::CustomMetricsCollector;
+use crate::get_info::gpu::GpuCollector;
+use crate::get_info::load::LoadCollector;
+use crate::get_info::mem::{DiskCollector, DiskFilter, MemCollector};
+use crate::get_info::network::listening::ListeningCollector;
+use crate::get_info::network::{ConnectionsCollector, NetworkCollector};
+use crate::get_info::pressure::PressureCollector;
+use crate::get_info::processes::TopProcessCollector;
+use crate::get_info::sensors::SensorCollector;
+use crate::watchdog::Watchdog;
+use log::{info, trace};
 use std::fs;
+use std::time::Duration;
 use sysinfo::System;
+use tokio::sync::mpsc::Receiver;
 
+pub mod cgroup;
+pub mod container;
 pub mod cpu;
+pub mod cpu_inventory;
+pub mod custom;
+pub mod disk_io;
+pub mod gpu;
+pub mod hardware;
 pub mod ip;
 pub mod load;
 pub mod mem;
 pub mod network;
 pub mod os;
+pub mod pressure;
+pub mod processes;
+pub mod sensors;
+pub mod stun;
 
+/// A metrics source that owns whatever state it needs between samples.
+///
+/// Each call to `collect` refreshes that state and returns one sample.
+pub trait Collector {
+    type Output;
+
+    fn collect(&mut self) -> Self::Output;
+}
+
+#[must_use]
 pub fn realtime_uptime() -> u64 {
     let uptime = System::uptime();
     trace!("REALTIME UPTIME successfully retrieved: {uptime}");
     uptime
 }
 
+#[must_use]
 pub fn realtime_process() -> u64 {
     let mut process_count = 0;
 
@@ -36,3 +73,98 @@ pub fn realtime_process() -> u64 {
     trace!("REALTIME PROCESS successfully retrieved: {process_count}");
     process_count
 }
+
+#[derive(Debug, Default)]
+pub struct UptimeCollector;
+
+impl Collector for UptimeCollector {
+    type Output = u64;
+
+    fn collect(&mut self) -> u64 {
+        realtime_uptime()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProcessCollector;
+
+impl Collector for ProcessCollector {
+    type Output = u64;
+
+    fn collect(&mut self) -> u64 {
+        realtime_process()
+    }
+}
+
+/// All collectors needed to build a `RealTimeInfo`, kept alive across samples
+/// so that rate-based metrics (CPU usage, network speed) have a previous point.
+pub struct RealTimeCollectors {
+    pub cpu: CpuCollector,
+    pub mem: MemCollector,
+    pub disk: DiskCollector,
+    pub load: LoadCollector,
+    pub network: NetworkCollector,
+    pub connections: ConnectionsCollector,
+    /// Only set when `listening_interval` is greater than 0
+    pub listening: Option<ListeningCollector>,
+    /// Only set when `gpu_metrics` is enabled
+    pub gpu: Option<GpuCollector>,
+    /// Only set when `cgroup_metrics` is enabled and a cgroup was found
+    pub cgroup: Option<CgroupCollector>,
+    pub sensors: SensorCollector,
+    pub pressure: PressureCollector,
+    pub process: ProcessCollector,
+    /// Only set when `top_processes` is greater than 0
+    pub top_processes: Option<TopProcessCollector>,
+    pub uptime: UptimeCollector,
+    /// Only set when the config lists something to watch
+    pub watchdog: Option<Watchdog>,
+    /// Only set when custom metric commands or a textfile directory are configured
+    pub custom_metrics: Option<CustomMetricsCollector>,
+}
+
+impl RealTimeCollectors {
+    /// `network_saver_rx` carries persisted traffic totals; pass `None` to report
+    /// interface counters since startup instead.
+    #[must_use]
+    pub fn new(config: &UserConfig, network_saver_rx: Option<Receiver<(u64, u64)>>) -> Self {
+        Self {
+            cpu: CpuCollector::new(),
+            mem: MemCollector::new(config.mem_used_mode.clone()),
+            disk: DiskCollector::new(DiskFilter::from_config(config)),
+            load: LoadCollector,
+            network: NetworkCollector::new(network_saver_rx, config.network_interfaces.clone()),
+            connections: ConnectionsCollector,
+            listening: (config.listening_interval > 0)
+                .then(|| ListeningCollector::new(Duration::from_secs(config.listening_interval))),
+            gpu: GpuCollector::from_config(config),
+            cgroup: cgroup_collector(config),
+            sensors: SensorCollector::new(),
+            pressure: PressureCollector::new(),
+            process: ProcessCollector,
+            top_processes: (config.top_processes > 0).then(|| {
+                TopProcessCollector::new(
+                    config.top_processes as usize,
+                    Duration::from_secs(config.top_processes_interval),
+                )
+            }),
+            uptime: UptimeCollector,
+            watchdog: Watchdog::from_config(config),
+            custom_metrics: CustomMetricsCollector::from_config(config),
+        }
+    }
+}
+
+fn cgroup_collector(config: &UserConfig) -> Option<CgroupCollector> {
+    if !config.cgroup_metrics {
+        return None;
+    }
+    let cgroup = Cgroup::detect()?;
+    info!(
+        "Reporting metrics relative to cgroup {:?} limits: {:?} cores, {:?} bytes memory",
+        cgroup.version,
+        cgroup.cpu_limit(),
+        cgroup.memory_limit()
+    );
+    Some(CgroupCollector::new(cgroup))
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::Path;
+
+pub const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// What `/sys/class/net/<name>` adds on top of sysinfo's counters
+#[derive(Debug, Clone, Default)]
+pub struct SysfsInterface {
+    /// `operstate`: up, down, dormant, lowerlayerdown, unknown...
+    pub state: Option<String>,
+    pub mtu: Option<u64>,
+    /// Link speed in Mbit/s
+    pub speed: Option<u64>,
+    pub rx_dropped: Option<u64>,
+    pub tx_dropped: Option<u64>,
+}
+
+/// Read one interface's attributes below `sys_class_net`; missing files stay `None`
+#[must_use]
+pub fn read_sysfs_interface(sys_class_net: &Path, name: &str) -> SysfsInterface {
+    let dir = sys_class_net.join(name);
+    let read = |file: &str| {
+        // `speed` fails with EINVAL while the link is down
+        Some(fs::read_to_string(dir.join(file)).ok()?.trim().to_string())
+    };
+    let number = |file: &str| read(file)?.parse::<u64>().ok();
+
+    SysfsInterface {
+        state: read("operstate"),
+        mtu: number("mtu"),
+        // Virtual interfaces report -1, which fails to parse as u64
+        speed: number("speed").filter(|speed| *speed > 0),
+        rx_dropped: number("statistics/rx_dropped"),
+        tx_dropped: number("statistics/tx_dropped"),
+    }
+}
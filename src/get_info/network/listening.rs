@@ -0,0 +1,355 @@
+use crate::data_struct::ListeningSocket;
+use crate::get_info::Collector;
+use log::{debug, info, trace};
+use std::collections::{HashMap, HashSet};
+#[cfg(target_os = "linux")]
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "linux")]
+use super::netlink::InetDiagSocket;
+use std::path::{Path, PathBuf};
+
+const PROC_ROOT: &str = "/proc";
+
+#[cfg(target_os = "linux")]
+const TCP_CLOSE: u8 = 7;
+#[cfg(target_os = "linux")]
+const TCP_LISTEN: u8 = 10;
+
+/// Socket inode -> (pid, process name) for the `socket:[N]` links under
+/// `<proc_root>/*/fd` that point at one of `inodes`. Stops walking /proc once
+/// every inode has an owner.
+#[cfg(target_os = "linux")]
+#[must_use]
+pub fn socket_owners<S: BuildHasher>(
+    proc_root: &Path,
+    inodes: &HashSet<u32, S>,
+) -> HashMap<u32, (u32, String)> {
+    use crate::get_info::processes::pids;
+    use std::fs;
+
+    let mut pids = pids(proc_root);
+    // Sockets inherited across fork belong to the lowest pid, usually the parent
+    pids.sort_unstable();
+
+    let mut owners = HashMap::new();
+    for pid in pids {
+        if owners.len() == inodes.len() {
+            break;
+        }
+        let dir = proc_root.join(pid.to_string());
+        // Other users' fds are unreadable without root; skip those processes
+        let Ok(fds) = fs::read_dir(dir.join("fd")) else {
+            continue;
+        };
+        let mut name = None;
+        for fd in fds.flatten() {
+            let Some(inode) = fs::read_link(fd.path()).ok().and_then(|target| {
+                target
+                    .to_str()?
+                    .strip_prefix("socket:[")?
+                    .strip_suffix(']')?
+                    .parse::<u32>()
+                    .ok()
+            }) else {
+                continue;
+            };
+            if !inodes.contains(&inode) {
+                continue;
+            }
+            let name = name.get_or_insert_with(|| {
+                fs::read_to_string(dir.join("comm"))
+                    .map(|comm| comm.trim().to_string())
+                    .unwrap_or_default()
+            });
+            owners.entry(inode).or_insert_with(|| (pid, name.clone()));
+        }
+    }
+    owners
+}
+
+/// TCP sockets in LISTEN, and UDP sockets in CLOSE, which for UDP means bound
+/// but not connected. Port 0 was never bound.
+#[cfg(target_os = "linux")]
+fn is_listening(protocol: &str, socket: &InetDiagSocket) -> bool {
+    let state = if protocol == "tcp" {
+        TCP_LISTEN
+    } else {
+        TCP_CLOSE
+    };
+    socket.state == state && socket.local_port != 0
+}
+
+/// Listening TCP and unconnected bound UDP sockets, with their owning process
+/// from `proc_root` when visible
+#[cfg(target_os = "linux")]
+#[must_use]
+pub fn listening_sockets(proc_root: &Path) -> Vec<ListeningSocket> {
+    use super::netlink::inet_diag_sockets;
+
+    let mut sockets = Vec::new();
+    for (protocol, ipproto, state) in [
+        ("tcp", libc::IPPROTO_TCP, TCP_LISTEN),
+        ("udp", libc::IPPROTO_UDP, TCP_CLOSE),
+    ] {
+        for family in [libc::AF_INET, libc::AF_INET6] {
+            let Ok(found) = inet_diag_sockets(family as u8, ipproto as u8, 1 << state) else {
+                continue;
+            };
+            sockets.extend(
+                found
+                    .into_iter()
+                    .filter(|socket| is_listening(protocol, socket))
+                    .map(|socket| (protocol, socket)),
+            );
+        }
+    }
+
+    let inodes: HashSet<u32> = sockets.iter().map(|(_, socket)| socket.inode).collect();
+    let owners = socket_owners(proc_root, &inodes);
+    let mut listening: Vec<ListeningSocket> = sockets
+        .into_iter()
+        .map(|(protocol, socket)| {
+            let owner = owners.get(&socket.inode);
+            ListeningSocket {
+                protocol: protocol.to_string(),
+                address: socket.local.to_string(),
+                port: socket.local_port,
+                pid: owner.map(|(pid, _)| *pid),
+                process: owner.map(|(_, name)| name.clone()).unwrap_or_default(),
+            }
+        })
+        .collect();
+    sort_and_dedup(&mut listening);
+    listening
+}
+
+#[cfg(target_os = "windows")]
+pub fn listening_sockets(_proc_root: &Path) -> Vec<ListeningSocket> {
+    use netstat2::{
+        AddressFamilyFlags, ProtocolFlags, ProtocolSocketInfo, TcpState, iterate_sockets_info,
+    };
+
+    let Ok(sockets) = iterate_sockets_info(
+        AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+        ProtocolFlags::TCP | ProtocolFlags::UDP,
+    ) else {
+        return Vec::new();
+    };
+
+    let mut listening: Vec<ListeningSocket> = sockets
+        .flatten()
+        .filter_map(|info| {
+            let (protocol, address, port) = match info.protocol_socket_info {
+                ProtocolSocketInfo::Tcp(tcp) if tcp.state == TcpState::Listen => {
+                    ("tcp", tcp.local_addr, tcp.local_port)
+                }
+                ProtocolSocketInfo::Tcp(_) => return None,
+                ProtocolSocketInfo::Udp(udp) => ("udp", udp.local_addr, udp.local_port),
+            };
+            Some(ListeningSocket {
+                protocol: protocol.to_string(),
+                address: address.to_string(),
+                port,
+                pid: info.associated_pids.first().copied(),
+                process: String::new(),
+            })
+        })
+        .collect();
+    sort_and_dedup(&mut listening);
+    listening
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn listening_sockets(_proc_root: &Path) -> Vec<ListeningSocket> {
+    Vec::new()
+}
+
+/// `SO_REUSEPORT` groups show up once per socket; keep one entry per address
+fn sort_and_dedup(listening: &mut Vec<ListeningSocket>) {
+    listening
+        .sort_by(|a, b| (&a.protocol, a.port, &a.address).cmp(&(&b.protocol, b.port, &b.address)));
+    listening
+        .dedup_by(|a, b| a.protocol == b.protocol && a.address == b.address && a.port == b.port);
+}
+
+/// `tcp 0.0.0.0:22 (sshd, pid 812)`
+#[must_use]
+pub fn describe(socket: &ListeningSocket) -> String {
+    let address = if socket.address.contains(':') {
+        format!("[{}]:{}", socket.address, socket.port)
+    } else {
+        format!("{}:{}", socket.address, socket.port)
+    };
+    match socket.pid {
+        Some(pid) => format!(
+            "{} {address} ({}, pid {pid})",
+            socket.protocol, socket.process
+        ),
+        None => format!("{} {address}", socket.protocol),
+    }
+}
+
+/// Takes a listening socket inventory at most once per `interval` and logs
+/// ports that started or stopped listening since the previous one.
+pub struct ListeningCollector {
+    proc_root: PathBuf,
+    interval: Duration,
+    last_sample: Option<Instant>,
+    /// (protocol, address, port) seen in the previous inventory
+    known: HashSet<(String, String, u16)>,
+}
+
+impl ListeningCollector {
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self::with_proc_root(PathBuf::from(PROC_ROOT), interval)
+    }
+
+    #[must_use]
+    pub fn with_proc_root(proc_root: PathBuf, interval: Duration) -> Self {
+        Self {
+            proc_root,
+            interval,
+            last_sample: None,
+            known: HashSet::new(),
+        }
+    }
+}
+
+impl Collector for ListeningCollector {
+    /// `None` until `interval` has passed since the previous inventory
+    type Output = Option<Vec<ListeningSocket>>;
+
+    fn collect(&mut self) -> Option<Vec<ListeningSocket>> {
+        if self
+            .last_sample
+            .is_some_and(|last_sample| last_sample.elapsed() < self.interval)
+        {
+            return None;
+        }
+
+        let listening = listening_sockets(&self.proc_root);
+        let mut current: HashMap<(String, String, u16), &ListeningSocket> = listening
+            .iter()
+            .map(|socket| {
+                (
+                    (socket.protocol.clone(), socket.address.clone(), socket.port),
+                    socket,
+                )
+            })
+            .collect();
+
+        // The first inventory is the baseline, not a change
+        if self.last_sample.is_some() {
+            for (key, socket) in &current {
+                if !self.known.contains(key) {
+                    info!("New listening port: {}", describe(socket));
+                }
+            }
+            for (protocol, address, port) in &self.known {
+                if !current.contains_key(&(protocol.clone(), address.clone(), *port)) {
+                    debug!("Port stopped listening: {protocol} {address} {port}");
+                }
+            }
+        }
+
+        self.known = current.drain().map(|(key, _)| key).collect();
+        self.last_sample = Some(Instant::now());
+        trace!("REALTIME LISTENING successfully retrieved: {listening:?}");
+        Some(listening)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::os::unix::fs::symlink;
+
+    fn write_process(proc_root: &Path, pid: u32, comm: &str, fds: &[&str]) {
+        let dir = proc_root.join(pid.to_string());
+        fs::create_dir_all(dir.join("fd")).unwrap();
+        fs::write(dir.join("comm"), format!("{comm}\n")).unwrap();
+        for (fd, target) in fds.iter().enumerate() {
+            symlink(target, dir.join("fd").join(fd.to_string())).unwrap();
+        }
+    }
+
+    fn socket(state: u8, port: u16) -> InetDiagSocket {
+        InetDiagSocket {
+            state,
+            local: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            local_port: port,
+            inode: 1,
+        }
+    }
+
+    #[test]
+    fn socket_owners_maps_inodes_to_the_lowest_pid() {
+        let root = tempfile::tempdir().unwrap();
+        let proc_root = root.path();
+        // nginx workers inherit the master's listening socket
+        write_process(proc_root, 1200, "nginx", &["socket:[5001]", "/dev/null"]);
+        write_process(proc_root, 812, "nginx", &["socket:[5001]", "socket:[5002]"]);
+        write_process(
+            proc_root,
+            90,
+            "sshd",
+            &["pipe:[77]", "socket:[4001]", "anon_inode:[eventfd]"],
+        );
+        write_process(proc_root, 91, "chronyd", &["socket:[9999]"]);
+        fs::create_dir_all(proc_root.join("self")).unwrap();
+
+        let owners = socket_owners(proc_root, &HashSet::from([4001, 5001, 5002, 6001]));
+
+        assert_eq!(owners.len(), 3);
+        assert_eq!(owners[&4001], (90, "sshd".to_string()));
+        assert_eq!(owners[&5001], (812, "nginx".to_string()));
+        assert_eq!(owners[&5002], (812, "nginx".to_string()));
+        // Not asked for
+        assert!(!owners.contains_key(&9999));
+    }
+
+    #[test]
+    fn socket_owners_without_sockets_or_proc() {
+        let root = tempfile::tempdir().unwrap();
+        write_process(root.path(), 1, "init", &["socket:[1]"]);
+        assert!(socket_owners(root.path(), &HashSet::new()).is_empty());
+        assert!(socket_owners(&root.path().join("missing"), &HashSet::from([1])).is_empty());
+    }
+
+    #[test]
+    fn is_listening_keeps_tcp_listen_and_unconnected_udp() {
+        assert!(is_listening("tcp", &socket(TCP_LISTEN, 22)));
+        // ESTABLISHED and TIME_WAIT
+        assert!(!is_listening("tcp", &socket(1, 22)));
+        assert!(!is_listening("tcp", &socket(6, 22)));
+        // A closed TCP socket is not a listener even though UDP uses the same state
+        assert!(!is_listening("tcp", &socket(TCP_CLOSE, 22)));
+
+        assert!(is_listening("udp", &socket(TCP_CLOSE, 53)));
+        // A connected UDP socket reports ESTABLISHED
+        assert!(!is_listening("udp", &socket(1, 53)));
+        assert!(!is_listening("udp", &socket(TCP_CLOSE, 0)));
+    }
+
+    #[test]
+    fn describe_brackets_ipv6_addresses() {
+        let mut socket = ListeningSocket {
+            protocol: "tcp".to_string(),
+            address: IpAddr::V6(Ipv6Addr::UNSPECIFIED).to_string(),
+            port: 443,
+            pid: Some(812),
+            process: "nginx".to_string(),
+        };
+        assert_eq!(describe(&socket), "tcp [::]:443 (nginx, pid 812)");
+        socket.address = "127.0.0.53".to_string();
+        socket.protocol = "udp".to_string();
+        socket.port = 53;
+        socket.pid = None;
+        assert_eq!(describe(&socket), "udp 127.0.0.53:53");
+    }
+}
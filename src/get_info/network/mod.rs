@@ -1,59 +1,199 @@
-use crate::data_struct::{Connections, Network};
+use crate::data_struct::{Connections, InterfaceStats, Network, TcpStates};
+use crate::get_info::Collector;
+use crate::get_info::network::interfaces::{SYS_CLASS_NET, read_sysfs_interface};
 use log::trace;
-use sysinfo::Networks;
+use std::path::Path;
+use std::time::Instant;
+use sysinfo::{NetworkData, Networks};
 use tokio::sync::mpsc::Receiver;
 
+pub mod interfaces;
+pub mod listening;
 #[cfg(target_os = "linux")]
 mod netlink;
 pub mod network_saver;
 
-static mut LAST_NETWORK: (u64, u64) = (0, 0);
+pub struct NetworkCollector {
+    networks: Networks,
+    /// When `networks` was last refreshed; deltas are divided by the time since
+    /// then rather than the nominal tick, which drifts and is never reached by
+    /// a one-off sample
+    last_refresh: Instant,
+    /// Seconds between the last two refreshes
+    elapsed: f64,
+    network_saver_rx: Option<Receiver<(u64, u64)>>,
+    last_total: (u64, u64),
+    /// Interfaces reported individually; empty = those counted in the totals
+    interfaces: Vec<String>,
+}
+
+impl NetworkCollector {
+    #[must_use]
+    pub fn new(network_saver_rx: Option<Receiver<(u64, u64)>>, interfaces: Vec<String>) -> Self {
+        Self {
+            networks: Networks::new_with_refreshed_list(),
+            last_refresh: Instant::now(),
+            elapsed: 0.0,
+            network_saver_rx,
+            last_total: (0, 0),
+            interfaces,
+        }
+    }
 
-pub static mut DURATION: f64 = 0.0;
-pub fn realtime_network(
-    network: &Networks,
-    network_saver_rx: Option<&mut Receiver<(u64, u64)>>,
-) -> Network {
-    let (up, down, total_up, total_down) = filter_network(network);
+    pub fn realtime_network(&mut self) -> Network {
+        let (up, down, total_up, total_down) = filter_network(&self.networks);
 
-    if let Some(network_saver_rx) = network_saver_rx {
-        if let Ok(network_saver_rx) = network_saver_rx.try_recv() {
-            unsafe {
-                LAST_NETWORK = (network_saver_rx.0, network_saver_rx.1);
+        if let Some(network_saver_rx) = &mut self.network_saver_rx {
+            if let Ok(saved_total) = network_saver_rx.try_recv() {
+                self.last_total = saved_total;
             }
+        } else {
+            self.last_total = (total_up, total_down);
         }
-    } else {
-        unsafe {
-            LAST_NETWORK = (total_up, total_down);
-        }
-    }
 
-    unsafe {
         let network_info = Network {
-            up: (up as f64 / (DURATION / 1000.0)) as u64,
-            down: (down as f64 / (DURATION / 1000.0)) as u64,
-            total_up: LAST_NETWORK.0,
-            total_down: LAST_NETWORK.1,
+            up: self.rate(up),
+            down: self.rate(down),
+            total_up: self.last_total.0,
+            total_down: self.last_total.1,
         };
         trace!("REALTIME NETWORK successfully retrieved: {network_info:?}");
         network_info
     }
+
+    /// Bytes per second for a delta since the previous refresh
+    fn rate(&self, bytes: u64) -> u64 {
+        if self.elapsed > 0.0 {
+            (bytes as f64 / self.elapsed) as u64
+        } else {
+            0
+        }
+    }
+
+    #[must_use]
+    pub fn realtime_interfaces(&self) -> Vec<InterfaceStats> {
+        let mut interfaces: Vec<InterfaceStats> = self
+            .networks
+            .iter()
+            .filter(|(name, data)| {
+                if self.interfaces.is_empty() {
+                    !is_filtered_interface(name, data)
+                } else {
+                    self.interfaces.iter().any(|selected| selected == *name)
+                }
+            })
+            .map(|(name, data)| {
+                let sysfs = read_sysfs_interface(Path::new(SYS_CLASS_NET), name);
+                InterfaceStats {
+                    name: name.clone(),
+                    state: sysfs.state.unwrap_or_else(|| "unknown".to_string()),
+                    mtu: sysfs.mtu.unwrap_or_else(|| data.mtu()),
+                    speed: sysfs.speed,
+                    rx_rate: self.rate(data.received()),
+                    tx_rate: self.rate(data.transmitted()),
+                    rx_bytes: data.total_received(),
+                    tx_bytes: data.total_transmitted(),
+                    rx_packets: data.total_packets_received(),
+                    tx_packets: data.total_packets_transmitted(),
+                    rx_errors: data.total_errors_on_received(),
+                    tx_errors: data.total_errors_on_transmitted(),
+                    rx_dropped: sysfs.rx_dropped,
+                    tx_dropped: sysfs.tx_dropped,
+                }
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        trace!("REALTIME INTERFACES successfully retrieved: {interfaces:?}");
+        interfaces
+    }
+}
+
+impl Collector for NetworkCollector {
+    type Output = (Network, Vec<InterfaceStats>);
+
+    fn collect(&mut self) -> (Network, Vec<InterfaceStats>) {
+        self.networks.refresh(true);
+        self.elapsed = self.last_refresh.elapsed().as_secs_f64();
+        self.last_refresh = Instant::now();
+        (self.realtime_network(), self.realtime_interfaces())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConnectionsCollector;
+
+impl Collector for ConnectionsCollector {
+    type Output = Connections;
+
+    fn collect(&mut self) -> Connections {
+        realtime_connections()
+    }
 }
 
+/// TCP sockets of one address family counted by state. Falls back to
+/// /proc/net/tcp(6) when the kernel refuses the `inet_diag` dump, e.g. when
+/// the module is not loaded.
 #[cfg(target_os = "linux")]
+#[must_use]
+pub fn tcp_states(family: i32) -> TcpStates {
+    match netlink::inet_diag_sockets(
+        family as u8,
+        libc::IPPROTO_TCP as u8,
+        netlink::ALL_TCP_STATES,
+    ) {
+        Ok(sockets) => {
+            let mut states = TcpStates::default();
+            for socket in sockets {
+                states.add(socket.state);
+            }
+            states
+        }
+        Err(e) => {
+            let path = if family == libc::AF_INET6 {
+                "/proc/net/tcp6"
+            } else {
+                "/proc/net/tcp"
+            };
+            log::debug!("TCP inet_diag dump failed: {e}, counting sockets from {path}");
+            std::fs::read_to_string(path)
+                .map(|content| parse_proc_net_tcp(&content))
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Count TCP sockets by state from the content of /proc/net/tcp or /proc/net/tcp6
+#[must_use]
+pub fn parse_proc_net_tcp(content: &str) -> TcpStates {
+    let mut states = TcpStates::default();
+    // sl local_address rem_address st ...; the state is hex
+    for line in content.lines().skip(1) {
+        if let Some(state) = line
+            .split_whitespace()
+            .nth(3)
+            .and_then(|st| u8::from_str_radix(st, 16).ok())
+        {
+            states.add(state);
+        }
+    }
+    states
+}
+
+#[cfg(target_os = "linux")]
+#[must_use]
 pub fn realtime_connections() -> Connections {
     use netlink::connections_count_with_protocol;
-    let tcp4 =
-        connections_count_with_protocol(libc::AF_INET as u8, libc::IPPROTO_TCP as u8).unwrap_or(0);
-    let tcp6 =
-        connections_count_with_protocol(libc::AF_INET6 as u8, libc::IPPROTO_TCP as u8).unwrap_or(0);
+    let tcp_states_v4 = tcp_states(libc::AF_INET);
+    let tcp_states_v6 = tcp_states(libc::AF_INET6);
     let udp4 =
         connections_count_with_protocol(libc::AF_INET as u8, libc::IPPROTO_UDP as u8).unwrap_or(0);
     let udp6 =
         connections_count_with_protocol(libc::AF_INET6 as u8, libc::IPPROTO_UDP as u8).unwrap_or(0);
     let connections = Connections {
-        tcp: tcp4 + tcp6,
+        tcp: tcp_states_v4.established + tcp_states_v6.established,
         udp: udp4 + udp6,
+        tcp_states_v4,
+        tcp_states_v6,
     };
     trace!(
         "REALTIME CONNECTIONS successfully retrieved: {:?}",
@@ -68,16 +208,31 @@ pub fn realtime_connections() -> Connections {
     let proto_flags = ProtocolFlags::TCP | ProtocolFlags::UDP;
 
     let Ok(sockets_iterator) = iterate_sockets_info_without_pids(proto_flags) else {
-        let connections = Connections { tcp: 0, udp: 0 };
+        let connections = Connections {
+            tcp: 0,
+            udp: 0,
+            tcp_states_v4: TcpStates::default(),
+            tcp_states_v6: TcpStates::default(),
+        };
         trace!("REALTIME CONNECTIONS successfully retrieved: {connections:?}");
         return connections;
     };
 
     let (mut tcp_count, mut udp_count) = (0, 0);
+    let mut tcp_states_v4 = TcpStates::default();
+    let mut tcp_states_v6 = TcpStates::default();
 
     for info_result in sockets_iterator.flatten() {
         match info_result.protocol_socket_info {
-            ProtocolSocketInfo::Tcp(_) => tcp_count += 1,
+            ProtocolSocketInfo::Tcp(tcp) => {
+                tcp_count += 1;
+                let states = if tcp.local_addr.is_ipv4() {
+                    &mut tcp_states_v4
+                } else {
+                    &mut tcp_states_v6
+                };
+                states.add(windows_tcp_state(&tcp.state));
+            }
             ProtocolSocketInfo::Udp(_) => udp_count += 1,
         }
     }
@@ -85,14 +240,41 @@ pub fn realtime_connections() -> Connections {
     let connections = Connections {
         tcp: tcp_count,
         udp: udp_count,
+        tcp_states_v4,
+        tcp_states_v6,
     };
     trace!("REALTIME CONNECTIONS successfully retrieved: {connections:?}");
     connections
 }
 
+/// Map to the Linux kernel numbering used by `TcpStates::add`
+#[cfg(target_os = "windows")]
+fn windows_tcp_state(state: &netstat2::TcpState) -> u8 {
+    use netstat2::TcpState;
+    match state {
+        TcpState::Established => 1,
+        TcpState::SynSent => 2,
+        TcpState::SynReceived => 3,
+        TcpState::FinWait1 => 4,
+        TcpState::FinWait2 => 5,
+        TcpState::TimeWait => 6,
+        TcpState::Closed => 7,
+        TcpState::CloseWait => 8,
+        TcpState::LastAck => 9,
+        TcpState::Listen => 10,
+        TcpState::Closing => 11,
+        _ => 0,
+    }
+}
+
 #[cfg(not(any(target_os = "linux", target_os = "windows")))]
 pub fn realtime_connections() -> Connections {
-    let connections = Connections { tcp: 0, udp: 0 };
+    let connections = Connections {
+        tcp: 0,
+        udp: 0,
+        tcp_states_v4: TcpStates::default(),
+        tcp_states_v6: TcpStates::default(),
+    };
     trace!(
         "REALTIME CONNECTIONS successfully retrieved: {:?}",
         connections
@@ -100,23 +282,30 @@ pub fn realtime_connections() -> Connections {
     connections
 }
 
+/// Bridges, container veths, tunnels, loopback and interfaces without a MAC
+/// are left out of the traffic totals
+#[must_use]
+pub fn is_filtered_interface(name: &str, data: &NetworkData) -> bool {
+    static FILTER_KEYWORDS: &[&str] = &[
+        "br", "cni", "docker", "podman", "flannel", "lo", "veth", "virbr", "vmbr", "tap", "tun",
+        "fwln", "fwpr",
+    ];
+
+    FILTER_KEYWORDS
+        .iter()
+        .any(|&keyword| name.contains(keyword))
+        || data.mac_address().0 == [0, 0, 0, 0, 0, 0]
+}
+
+#[must_use]
 pub fn filter_network(network: &Networks) -> (u64, u64, u64, u64) {
     let mut total_up = 0;
     let mut total_down = 0;
     let mut up = 0;
     let mut down = 0;
 
-    static FILTER_KEYWORDS: &[&str] = &[
-        "br", "cni", "docker", "podman", "flannel", "lo", "veth", "virbr", "vmbr", "tap", "tun",
-        "fwln", "fwpr",
-    ];
-
     for (name, data) in network {
-        let should_filter = FILTER_KEYWORDS
-            .iter()
-            .any(|&keyword| name.contains(keyword));
-
-        if should_filter || data.mac_address().0 == [0, 0, 0, 0, 0, 0] {
+        if is_filtered_interface(name, data) {
             continue;
         }
 
@@ -128,3 +317,28 @@ pub fn filter_network(network: &Networks) -> (u64, u64, u64, u64) {
 
     (up, down, total_up, total_down)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_NET_TCP: &str = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 21346 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 33451 1 0000000000000000 100 0 0 10 0
+   2: 1401A8C0:0016 0A01A8C0:D431 01 00000000:00000000 02:0009B1A4 00000000     0        0 41234 4 0000000000000000 20 4 31 10 -1
+   3: 1401A8C0:9C40 22D8B85D:01BB 06 00000000:00000000 03:00000A3C 00000000     0        0 0 3 0000000000000000
+   4: 1401A8C0:9C42 22D8B85D:01BB 08 00000001:00000000 00:00000000 00000000  1000        0 51234 1 0000000000000000 20 4 0 10 -1
+";
+
+    #[test]
+    fn parse_proc_net_tcp_counts_hex_states() {
+        let states = parse_proc_net_tcp(PROC_NET_TCP);
+        assert_eq!(states.listen, 2);
+        assert_eq!(states.established, 1);
+        assert_eq!(states.time_wait, 1);
+        assert_eq!(states.close_wait, 1);
+        assert_eq!(states.syn_sent, 0);
+        assert_eq!(parse_proc_net_tcp("").established, 0);
+    }
+}
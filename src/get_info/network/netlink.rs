@@ -1,11 +1,12 @@
 use libc::{c_void, close, recvfrom, sendto, sockaddr, sockaddr_nl, socket};
 use std::io;
 use std::mem::{size_of, zeroed};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::os::fd::RawFd;
 use std::ptr;
 
 const SOCK_DIAG_BY_FAMILY: u16 = 20;
-const ALL_TCP_STATES: u32 = 0xffffffff;
+pub const ALL_TCP_STATES: u32 = 0xffffffff;
 const TCP_ESTABLISHED: u32 = 1;
 const NLMSG_HDRLEN: usize = size_of::<libc::nlmsghdr>();
 
@@ -35,6 +36,33 @@ struct InetDiagReqV2 {
     id: InetDiagSockId,
 }
 
+// from linux/inet_diag.h; some fields only exist to keep the layout
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(dead_code, clippy::struct_field_names)]
+struct InetDiagMsg {
+    idiag_family: u8,
+    idiag_state: u8,
+    idiag_timer: u8,
+    idiag_retrans: u8,
+    id: InetDiagSockId,
+    idiag_expires: u32,
+    idiag_rqueue: u32,
+    idiag_wqueue: u32,
+    idiag_uid: u32,
+    idiag_inode: u32,
+}
+
+/// One socket from an `inet_diag` dump
+#[derive(Debug, Clone)]
+pub struct InetDiagSocket {
+    /// Kernel TCP state (`TCP_ESTABLISHED` = 1 ... `TCP_NEW_SYN_RECV` = 12)
+    pub state: u8,
+    pub local: IpAddr,
+    pub local_port: u16,
+    pub inode: u32,
+}
+
 /// Entry: Count connection messages by protocol
 pub fn connections_count_with_protocol(family: u8, protocol: u8) -> io::Result<u64> {
     // Construct netlink header
@@ -75,12 +103,100 @@ pub fn connections_count_with_protocol(family: u8, protocol: u8) -> io::Result<u
     netlink_inet_diag_only_count(&msg)
 }
 
-fn netlink_inet_diag_only_count(request: &[u8]) -> io::Result<u64> {
+/// Entry: Dump sockets of a protocol whose state is in the `states` bitmask (`1 << state`)
+pub fn inet_diag_sockets(family: u8, protocol: u8, states: u32) -> io::Result<Vec<InetDiagSocket>> {
+    let hdr = libc::nlmsghdr {
+        nlmsg_len: 0,
+        nlmsg_type: SOCK_DIAG_BY_FAMILY,
+        nlmsg_flags: (libc::NLM_F_DUMP | libc::NLM_F_REQUEST) as u16,
+        nlmsg_seq: 0,
+        nlmsg_pid: 0,
+    };
+    let req = InetDiagReqV2 {
+        family,
+        protocol,
+        ext: 0,
+        pad: 0,
+        states,
+        id: InetDiagSockId {
+            idiag_sport: 0,
+            idiag_dport: 0,
+            idiag_src: [0; 4],
+            idiag_dst: [0; 4],
+            idiag_if: 0,
+            idiag_cookie: [0; 2],
+        },
+    };
+    let msg = serialize_netlink_message(&hdr, &req)?;
+
+    let guard = netlink_inet_diag_send(&msg)?;
+
+    // Large enough that the kernel never has to truncate a batch
+    let mut buf: Vec<u8> = vec![0u8; 32 * 1024];
+    let mut sockets = Vec::new();
+
+    loop {
+        let nr = unsafe {
+            recvfrom(
+                guard.0,
+                buf.as_mut_ptr().cast::<c_void>(),
+                buf.len(),
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        if nr < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut b = &buf[..nr as usize];
+
+        while b.len() >= NLMSG_HDRLEN {
+            let (dlen, at_end) = netlink_message_header(b)?;
+            if at_end {
+                return Ok(sockets);
+            }
+            if let Some(socket) = parse_inet_diag_msg(&b[NLMSG_HDRLEN..dlen]) {
+                sockets.push(socket);
+            }
+            b = &b[dlen..];
+        }
+    }
+}
+
+/// Decode the payload of one `SOCK_DIAG_BY_FAMILY` reply
+fn parse_inet_diag_msg(payload: &[u8]) -> Option<InetDiagSocket> {
+    if payload.len() < size_of::<InetDiagMsg>() {
+        return None;
+    }
+    let msg = unsafe { ptr::read_unaligned(payload.as_ptr().cast::<InetDiagMsg>()) };
+
+    // Addresses and ports are in network byte order
+    let mut src = [0u8; 16];
+    for (chunk, word) in src.chunks_exact_mut(4).zip(msg.id.idiag_src) {
+        chunk.copy_from_slice(&word.to_ne_bytes());
+    }
+    let local = match i32::from(msg.idiag_family) {
+        libc::AF_INET => IpAddr::V4(Ipv4Addr::new(src[0], src[1], src[2], src[3])),
+        libc::AF_INET6 => IpAddr::V6(Ipv6Addr::from(src)),
+        _ => return None,
+    };
+
+    Some(InetDiagSocket {
+        state: msg.idiag_state,
+        local,
+        local_port: u16::from_be(msg.id.idiag_sport),
+        inode: msg.idiag_inode,
+    })
+}
+
+/// Open a `NETLINK_SOCK_DIAG` socket and send `request` to the kernel
+fn netlink_inet_diag_send(request: &[u8]) -> io::Result<FdGuard> {
     let fd = unsafe { socket(libc::AF_NETLINK, libc::SOCK_RAW, libc::NETLINK_SOCK_DIAG) };
     if fd < 0 {
         return Err(io::Error::last_os_error());
     }
-    let _guard = FdGuard(fd);
+    let guard = FdGuard(fd);
 
     let mut addr: sockaddr_nl = unsafe { zeroed() };
     addr.nl_family = libc::AF_NETLINK as u16;
@@ -102,6 +218,13 @@ fn netlink_inet_diag_only_count(request: &[u8]) -> io::Result<u64> {
         return Err(io::Error::last_os_error());
     }
 
+    Ok(guard)
+}
+
+fn netlink_inet_diag_only_count(request: &[u8]) -> io::Result<u64> {
+    let guard = netlink_inet_diag_send(request)?;
+    let fd = guard.0;
+
     // Prepare read buffer
     let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
     let mut buf: Vec<u8> = vec![0u8; page_size];
@@ -158,7 +281,8 @@ fn count_netlink_messages(mut b: &[u8]) -> io::Result<(u64, bool)> {
     Ok((msgs, done))
 }
 
-/// Parse nlmsghdr of the current slice, return (aligned length of this message, whether DONE/ERROR)
+/// Parse nlmsghdr of the current slice, return (aligned length of this message, whether DONE/ERROR);
+/// an `NLMSG_ERROR` carrying an errno is returned as that error
 fn netlink_message_header(b: &[u8]) -> io::Result<(usize, bool)> {
     if b.len() < NLMSG_HDRLEN {
         return Err(io::Error::from_raw_os_error(libc::EINVAL));
@@ -173,7 +297,21 @@ fn netlink_message_header(b: &[u8]) -> io::Result<(usize, bool)> {
         return Err(io::Error::from_raw_os_error(libc::EINVAL));
     }
 
-    if h.nlmsg_type == libc::NLMSG_DONE as u16 || h.nlmsg_type == libc::NLMSG_ERROR as u16 {
+    if h.nlmsg_type == libc::NLMSG_ERROR as u16 {
+        // struct nlmsgerr starts with a negative errno; 0 is only an acknowledgement.
+        // A non-zero errno means the kernel aborted the dump (EPERM, ENOENT without
+        // the inet_diag module, ...), so whatever was received is incomplete.
+        let errno = b
+            .get(NLMSG_HDRLEN..NLMSG_HDRLEN + size_of::<i32>())
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+        let errno = i32::from_ne_bytes(errno.try_into().unwrap_or_default());
+        if errno != 0 {
+            return Err(io::Error::from_raw_os_error(-errno));
+        }
+        return Ok((l, true));
+    }
+
+    if h.nlmsg_type == libc::NLMSG_DONE as u16 {
         return Ok((l, true));
     }
 
@@ -220,3 +358,111 @@ impl Drop for FdGuard {
         unsafe { close(self.0) };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `inet_diag_msg` as the kernel lays it out, with `src` in network order
+    fn diag_msg(family: i32, state: u8, sport: u16, src: &[u8], inode: u32) -> Vec<u8> {
+        let mut msg = vec![family as u8, state, 0, 0];
+        msg.extend(sport.to_be_bytes());
+        msg.extend(443u16.to_be_bytes());
+        let mut addr = [0u8; 16];
+        addr[..src.len()].copy_from_slice(src);
+        msg.extend(addr);
+        msg.extend([0u8; 16 + 4 + 8]); // idiag_dst, idiag_if, idiag_cookie
+        msg.extend([0u8; 16]); // expires, rqueue, wqueue, uid
+        msg.extend(inode.to_ne_bytes());
+        msg
+    }
+
+    fn nlmsg(nlmsg_type: i32, payload: &[u8]) -> Vec<u8> {
+        let len = NLMSG_HDRLEN + payload.len();
+        let mut msg = Vec::new();
+        msg.extend((len as u32).to_ne_bytes());
+        msg.extend((nlmsg_type as u16).to_ne_bytes());
+        msg.extend(0u16.to_ne_bytes()); // flags
+        msg.extend(0u32.to_ne_bytes()); // seq
+        msg.extend(0u32.to_ne_bytes()); // pid
+        msg.extend(payload);
+        msg.resize(nlm_align_of(i32::try_from(len).unwrap()) as usize, 0);
+        msg
+    }
+
+    #[test]
+    fn inet_diag_msg_layout_matches_the_kernel() {
+        assert_eq!(size_of::<InetDiagMsg>(), 72);
+        assert_eq!(diag_msg(libc::AF_INET, 1, 80, &[], 0).len(), 72);
+    }
+
+    #[test]
+    fn parse_inet_diag_msg_ipv4() {
+        let socket = parse_inet_diag_msg(&diag_msg(
+            libc::AF_INET,
+            10,
+            8080,
+            &[192, 168, 1, 20],
+            123_456,
+        ))
+        .unwrap();
+        assert_eq!(socket.state, 10);
+        assert_eq!(socket.local, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20)));
+        // 8080 is 0x1f90 on the wire; read natively it would be 0x901f
+        assert_eq!(socket.local_port, 8080);
+        assert_eq!(socket.inode, 123_456);
+    }
+
+    #[test]
+    fn parse_inet_diag_msg_ipv6() {
+        let addr: Ipv6Addr = "2001:db8::1:2".parse().unwrap();
+        let socket =
+            parse_inet_diag_msg(&diag_msg(libc::AF_INET6, 1, 0x0102, &addr.octets(), 7)).unwrap();
+        assert_eq!(socket.state, 1);
+        assert_eq!(socket.local, IpAddr::V6(addr));
+        assert_eq!(socket.local_port, 0x0102);
+        assert_eq!(socket.inode, 7);
+    }
+
+    #[test]
+    fn parse_inet_diag_msg_rejects_truncated_and_unknown_messages() {
+        let msg = diag_msg(libc::AF_INET, 1, 22, &[10, 0, 0, 1], 1);
+        assert!(parse_inet_diag_msg(&msg[..msg.len() - 1]).is_none());
+        assert!(parse_inet_diag_msg(&[]).is_none());
+        assert!(parse_inet_diag_msg(&diag_msg(libc::AF_UNIX, 1, 22, &[], 1)).is_none());
+    }
+
+    #[test]
+    fn netlink_message_header_walks_a_dump() {
+        let mut batch = nlmsg(
+            i32::from(SOCK_DIAG_BY_FAMILY),
+            &diag_msg(libc::AF_INET, 1, 22, &[], 1),
+        );
+        let first_len = batch.len();
+        batch.extend(nlmsg(libc::NLMSG_DONE, &0i32.to_ne_bytes()));
+
+        assert_eq!(netlink_message_header(&batch).unwrap(), (first_len, false));
+        assert!(netlink_message_header(&batch[first_len..]).unwrap().1);
+        assert_eq!(count_netlink_messages(&batch).unwrap(), (2, true));
+        assert!(netlink_message_header(&batch[..NLMSG_HDRLEN - 1]).is_err());
+    }
+
+    #[test]
+    fn netlink_message_header_returns_the_errno_of_an_aborted_dump() {
+        let mut payload = (-libc::ENOENT).to_ne_bytes().to_vec();
+        payload.extend([0u8; NLMSG_HDRLEN]); // the request header echoed back
+        let error = netlink_message_header(&nlmsg(libc::NLMSG_ERROR, &payload)).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::ENOENT));
+
+        let mut ack = 0i32.to_ne_bytes().to_vec();
+        ack.extend([0u8; NLMSG_HDRLEN]);
+        assert!(
+            netlink_message_header(&nlmsg(libc::NLMSG_ERROR, &ack))
+                .unwrap()
+                .1
+        );
+
+        // Too short to hold the errno
+        assert!(netlink_message_header(&nlmsg(libc::NLMSG_ERROR, &[])).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use log::trace;
+use std::fs;
+use std::path::Path;
 use sysinfo::System;
 
 #[derive(Debug)]
@@ -8,22 +10,205 @@ pub struct OsInfo {
     pub virtualization: String,
 }
 
-pub async fn os() -> OsInfo {
-    let os = format!(
-        "{} {}",
-        System::name().unwrap_or_default(),
-        System::os_version().unwrap_or_default()
-    );
+/// The os-release fields we report, see os-release(5)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OsRelease {
+    pub name: String,
+    pub pretty_name: String,
+    pub id: String,
+    pub id_like: Vec<String>,
+    pub version_id: String,
+    pub version_codename: String,
+}
+
+/// Unquote a value the way a shell would, handling the escapes os-release(5) allows
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let quote = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) if value.len() >= 2 && value.ends_with(quote) => quote,
+        _ => return value.to_string(),
+    };
+    let inner = &value[1..value.len() - 1];
+    if quote == '\'' {
+        return inner.to_string();
+    }
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+pub fn parse_os_release(content: &str) -> OsRelease {
+    let mut release = OsRelease::default();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = unquote(value);
+        match key.trim() {
+            "NAME" => release.name = value,
+            "PRETTY_NAME" => release.pretty_name = value,
+            "ID" => release.id = value,
+            "ID_LIKE" => release.id_like = value.split_whitespace().map(str::to_string).collect(),
+            "VERSION_ID" => release.version_id = value,
+            "VERSION_CODENAME" => release.version_codename = value,
+            _ => {}
+        }
+    }
+    if release.pretty_name.is_empty() {
+        release.pretty_name = format!("{} {}", release.name, release.version_id)
+            .trim()
+            .to_string();
+    }
+    release
+}
+
+/// Content of /etc/redhat-release such as `CentOS release 6.10 (Final)`
+#[must_use]
+pub fn parse_redhat_release(content: &str) -> OsRelease {
+    let pretty_name = content
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let name = pretty_name
+        .split(" release ")
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let version_id = pretty_name
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or_default()
+        .to_string();
+    let id = match name.to_lowercase() {
+        name if name.contains("centos") => "centos",
+        name if name.contains("fedora") => "fedora",
+        name if name.contains("red hat") => "rhel",
+        name if name.contains("rocky") => "rocky",
+        name if name.contains("alma") => "almalinux",
+        _ => "rhel",
+    }
+    .to_string();
+    OsRelease {
+        name,
+        pretty_name,
+        id,
+        id_like: vec!["rhel".to_string(), "fedora".to_string()],
+        version_id,
+        version_codename: String::new(),
+    }
+}
+
+/// Content of `/etc/debian_version`: a number such as `9.13`, or `bookworm/sid` on testing
+#[must_use]
+pub fn parse_debian_version(content: &str) -> OsRelease {
+    let version = content.trim();
+    let (version_id, version_codename) = if version.starts_with(|c: char| c.is_ascii_digit()) {
+        (version.to_string(), String::new())
+    } else {
+        (
+            String::new(),
+            version.split('/').next().unwrap_or_default().to_string(),
+        )
+    };
+    let pretty_name = format!("Debian GNU/Linux {version}");
+    OsRelease {
+        name: "Debian GNU/Linux".to_string(),
+        pretty_name,
+        id: "debian".to_string(),
+        id_like: Vec::new(),
+        version_id,
+        version_codename,
+    }
+}
+
+/// Read the distribution from the files below `root`, newest standard first
+#[must_use]
+pub fn read_os_release(root: &Path) -> Option<OsRelease> {
+    let read = |path: &str| fs::read_to_string(root.join(path)).ok();
+    read("etc/os-release")
+        .or_else(|| read("usr/lib/os-release"))
+        .map(|content| parse_os_release(&content))
+        .or_else(|| read("etc/redhat-release").map(|content| parse_redhat_release(&content)))
+        .or_else(|| read("etc/debian_version").map(|content| parse_debian_version(&content)))
+}
+
+/// Fill `{pretty_name}`, `{name}`, `{id}`, `{id_like}`, `{version_id}` and `{codename}`.
+/// Falls back to the pretty name when the result would be empty.
+#[must_use]
+pub fn format_os(format: &str, release: &OsRelease) -> String {
+    let formatted = format
+        .replace("{pretty_name}", &release.pretty_name)
+        .replace("{name}", &release.name)
+        .replace("{id_like}", &release.id_like.join(" "))
+        .replace("{id}", &release.id)
+        .replace("{version_id}", &release.version_id)
+        .replace("{codename}", &release.version_codename);
+    let formatted = formatted.split_whitespace().collect::<Vec<_>>().join(" ");
+    if formatted.is_empty() {
+        release.pretty_name.clone()
+    } else {
+        formatted
+    }
+}
+
+/// sysinfo's view, used where there is no os-release
+fn sysinfo_release() -> OsRelease {
+    let name = System::name().unwrap_or_default();
+    let version_id = System::os_version().unwrap_or_default();
+    OsRelease {
+        pretty_name: format!("{name} {version_id}").trim().to_string(),
+        id: System::distribution_id(),
+        name,
+        id_like: Vec::new(),
+        version_id,
+        version_codename: String::new(),
+    }
+}
+
+pub async fn os(format: &str) -> OsInfo {
+    let release = if cfg!(target_os = "linux") {
+        read_os_release(Path::new("/"))
+    } else {
+        None
+    }
+    .unwrap_or_else(sysinfo_release);
+    let os = format_os(format, &release);
     let kernel_version = System::kernel_version().unwrap_or("Unknown".to_string());
 
     let virt = {
         #[cfg(target_os = "linux")]
         {
-            heim_virt::detect()
+            use crate::get_info::container::{
+                detect_container, detect_hypervisor_dmi, format_virtualization,
+            };
+
+            // heim_virt stops at the first layer it finds, which is the container if any
+            let detected = heim_virt::detect()
                 .await
-                .unwrap_or(heim_virt::Virtualization::Unknown)
-                .as_str()
-                .to_string()
+                .unwrap_or(heim_virt::Virtualization::Unknown);
+            let hypervisor = if detected.is_vm() {
+                Some(detected.as_str())
+            } else {
+                detect_hypervisor_dmi()
+            };
+            let container = detect_container().or_else(|| {
+                detected
+                    .is_container()
+                    .then(|| detected.as_str().to_string())
+            });
+            format_virtualization(hypervisor, container.as_deref())
         }
 
         #[cfg(target_os = "windows")]
@@ -77,3 +262,217 @@ pub async fn os() -> OsInfo {
 
     os_info
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALPINE: &str = r#"NAME="Alpine Linux"
+ID=alpine
+VERSION_ID=3.19.1
+PRETTY_NAME="Alpine Linux v3.19"
+HOME_URL="https://alpinelinux.org/"
+BUG_REPORT_URL="https://gitlab.alpinelinux.org/alpine/aports/-/issues"
+"#;
+
+    const ARCH: &str = r#"NAME="Arch Linux"
+PRETTY_NAME="Arch Linux"
+ID=arch
+BUILD_ID=rolling
+ANSI_COLOR="38;2;23;147;209"
+LOGO=archlinux-logo
+"#;
+
+    const NIXOS: &str = r#"ANSI_COLOR="1;34"
+BUILD_ID="23.11.20240115.b8dd8be"
+DOCUMENTATION_URL="https://nixos.org/learn.html"
+ID=nixos
+LOGO="nix-snowflake"
+NAME=NixOS
+PRETTY_NAME="NixOS 23.11 (Tapir)"
+VERSION="23.11 (Tapir)"
+VERSION_CODENAME=tapir
+VERSION_ID="23.11"
+"#;
+
+    const UBUNTU: &str = r#"# Comments and blank lines are allowed
+
+PRETTY_NAME="Ubuntu 22.04.4 LTS"
+NAME="Ubuntu"
+VERSION_ID="22.04"
+VERSION_CODENAME=jammy
+ID=ubuntu
+ID_LIKE=debian
+"#;
+
+    fn release(
+        name: &str,
+        pretty_name: &str,
+        id: &str,
+        id_like: &[&str],
+        version_id: &str,
+        version_codename: &str,
+    ) -> OsRelease {
+        OsRelease {
+            name: name.to_string(),
+            pretty_name: pretty_name.to_string(),
+            id: id.to_string(),
+            id_like: id_like.iter().map(ToString::to_string).collect(),
+            version_id: version_id.to_string(),
+            version_codename: version_codename.to_string(),
+        }
+    }
+
+    #[test]
+    fn unquotes_like_a_shell() {
+        assert_eq!(unquote("plain"), "plain");
+        assert_eq!(unquote("  \"padded\"  "), "padded");
+        assert_eq!(unquote("\"Debian GNU/Linux\""), "Debian GNU/Linux");
+        assert_eq!(unquote("'single \\\"quoted\\\"'"), "single \\\"quoted\\\"");
+        assert_eq!(
+            unquote(r#""say \"hi\" for \$5 \`now\` \\ done""#),
+            r#"say "hi" for $5 `now` \ done"#
+        );
+        assert_eq!(unquote("\"\""), "");
+        // Unbalanced quotes are kept as they are
+        assert_eq!(unquote("\"open"), "\"open");
+        assert_eq!(unquote("'"), "'");
+        assert_eq!(unquote("\"mixed'"), "\"mixed'");
+    }
+
+    #[test]
+    fn parses_os_release_files() {
+        assert_eq!(
+            parse_os_release(ALPINE),
+            release(
+                "Alpine Linux",
+                "Alpine Linux v3.19",
+                "alpine",
+                &[],
+                "3.19.1",
+                ""
+            )
+        );
+        assert_eq!(
+            parse_os_release(ARCH),
+            release("Arch Linux", "Arch Linux", "arch", &[], "", "")
+        );
+        assert_eq!(
+            parse_os_release(NIXOS),
+            release(
+                "NixOS",
+                "NixOS 23.11 (Tapir)",
+                "nixos",
+                &[],
+                "23.11",
+                "tapir"
+            )
+        );
+        assert_eq!(
+            parse_os_release(UBUNTU),
+            release(
+                "Ubuntu",
+                "Ubuntu 22.04.4 LTS",
+                "ubuntu",
+                &["debian"],
+                "22.04",
+                "jammy"
+            )
+        );
+    }
+
+    #[test]
+    fn builds_pretty_name_when_missing() {
+        let parsed = parse_os_release(
+            "NAME=Minimal\nID=minimal\nVERSION_ID=1.0\nID_LIKE=\"rhel  fedora\"\n",
+        );
+        assert_eq!(parsed.pretty_name, "Minimal 1.0");
+        assert_eq!(parsed.id_like, ["rhel", "fedora"]);
+        assert_eq!(parse_os_release("NAME=Minimal\n").pretty_name, "Minimal");
+    }
+
+    #[test]
+    fn parses_redhat_release() {
+        assert_eq!(
+            parse_redhat_release("CentOS release 6.10 (Final)\n"),
+            release(
+                "CentOS",
+                "CentOS release 6.10 (Final)",
+                "centos",
+                &["rhel", "fedora"],
+                "6.10",
+                ""
+            )
+        );
+        let rhel =
+            parse_redhat_release("Red Hat Enterprise Linux Server release 6.10 (Santiago)\n");
+        assert_eq!(rhel.id, "rhel");
+        assert_eq!(rhel.name, "Red Hat Enterprise Linux Server");
+        assert_eq!(rhel.version_id, "6.10");
+    }
+
+    #[test]
+    fn parses_debian_version() {
+        assert_eq!(
+            parse_debian_version("9.13\n"),
+            release(
+                "Debian GNU/Linux",
+                "Debian GNU/Linux 9.13",
+                "debian",
+                &[],
+                "9.13",
+                ""
+            )
+        );
+        assert_eq!(
+            parse_debian_version("trixie/sid\n"),
+            release(
+                "Debian GNU/Linux",
+                "Debian GNU/Linux trixie/sid",
+                "debian",
+                &[],
+                "",
+                "trixie"
+            )
+        );
+    }
+
+    #[test]
+    fn reads_release_files_in_order() {
+        let root = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        assert_eq!(read_os_release(root.path()), None);
+
+        write("etc/debian_version", "trixie/sid\n");
+        assert_eq!(
+            read_os_release(root.path()).unwrap().version_codename,
+            "trixie"
+        );
+
+        write("etc/redhat-release", "CentOS release 6.10 (Final)\n");
+        assert_eq!(read_os_release(root.path()).unwrap().id, "centos");
+
+        write("usr/lib/os-release", NIXOS);
+        assert_eq!(read_os_release(root.path()).unwrap().id, "nixos");
+
+        write("etc/os-release", ALPINE);
+        assert_eq!(read_os_release(root.path()).unwrap().id, "alpine");
+    }
+
+    #[test]
+    fn formats_os_names() {
+        let ubuntu = parse_os_release(UBUNTU);
+        assert_eq!(format_os("{pretty_name}", &ubuntu), "Ubuntu 22.04.4 LTS");
+        assert_eq!(
+            format_os("{name} {version_id} ({codename}, like {id_like})", &ubuntu),
+            "Ubuntu 22.04 (jammy, like debian)"
+        );
+        let arch = parse_os_release(ARCH);
+        assert_eq!(format_os("{id} {version_id}", &arch), "arch");
+        assert_eq!(format_os("{version_id}", &arch), "Arch Linux");
+    }
+}
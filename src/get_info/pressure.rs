@@ -0,0 +1,178 @@
+use crate::data_struct::{Pressure, PressureResource, PressureStall};
+use crate::get_info::Collector;
+use crate::get_info::cgroup::Cgroup;
+use log::{debug, trace};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const PROC_PRESSURE: &str = "/proc/pressure";
+
+/// Parse one line such as `some avg10=0.12 avg60=0.05 avg300=0.01 total=123456`
+fn parse_stall(line: &str) -> Option<(&str, PressureStall)> {
+    let mut fields = line.split_whitespace();
+    let kind = fields.next()?;
+
+    let mut stall = PressureStall {
+        avg10: 0.0,
+        avg60: 0.0,
+        avg300: 0.0,
+        total: 0,
+    };
+    for field in fields {
+        let (key, value) = field.split_once('=')?;
+        match key {
+            "avg10" => stall.avg10 = value.parse().ok()?,
+            "avg60" => stall.avg60 = value.parse().ok()?,
+            "avg300" => stall.avg300 = value.parse().ok()?,
+            "total" => stall.total = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    Some((kind, stall))
+}
+
+/// Parse the content of a PSI file (`/proc/pressure/*` or a cgroup `*.pressure`)
+pub fn parse_pressure(content: &str) -> Option<PressureResource> {
+    let mut some = None;
+    let mut full = None;
+    for (kind, stall) in content.lines().filter_map(parse_stall) {
+        match kind {
+            "some" => some = Some(stall),
+            "full" => full = Some(stall),
+            _ => {}
+        }
+    }
+    Some(PressureResource { some: some?, full })
+}
+
+/// Read `<dir>/cpu<suffix>` and friends; missing files leave the resource `None`
+#[must_use]
+pub fn read_pressure(dir: &Path, suffix: &str) -> Pressure {
+    let read = |resource: &str| {
+        // Reading fails with EOPNOTSUPP when booted with psi=0
+        let content = fs::read_to_string(dir.join(format!("{resource}{suffix}"))).ok()?;
+        parse_pressure(&content)
+    };
+    Pressure {
+        cpu: read("cpu"),
+        memory: read("memory"),
+        io: read("io"),
+    }
+}
+
+pub struct PressureCollector {
+    host_dir: PathBuf,
+    cgroup_dir: Option<PathBuf>,
+}
+
+impl PressureCollector {
+    #[must_use]
+    pub fn new() -> Self {
+        let cgroup_dir = Cgroup::detect()
+            .and_then(|cgroup| cgroup.pressure_dir().map(Path::to_path_buf))
+            // The root cgroup has no pressure files; its PSI is /proc/pressure
+            .filter(|dir| dir.join("cpu.pressure").exists());
+        Self::with_dirs(PathBuf::from(PROC_PRESSURE), cgroup_dir)
+    }
+
+    #[must_use]
+    pub fn with_dirs(host_dir: PathBuf, cgroup_dir: Option<PathBuf>) -> Self {
+        if read_pressure(&host_dir, "").cpu.is_none() {
+            debug!("PSI is not available, pressure will not be reported");
+        }
+        Self {
+            host_dir,
+            cgroup_dir,
+        }
+    }
+}
+
+impl Default for PressureCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Collector for PressureCollector {
+    type Output = (Pressure, Option<Pressure>);
+
+    fn collect(&mut self) -> (Pressure, Option<Pressure>) {
+        let pressure = read_pressure(&self.host_dir, "");
+        let cgroup_pressure = self
+            .cgroup_dir
+            .as_ref()
+            .map(|dir| read_pressure(dir, ".pressure"));
+        trace!("REALTIME PRESSURE successfully retrieved: {pressure:?} {cgroup_pressure:?}");
+        (pressure, cgroup_pressure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMORY_PRESSURE: &str = "\
+some avg10=1.50 avg60=0.75 avg300=0.25 total=987654
+full avg10=0.50 avg60=0.25 avg300=0.00 total=123456
+";
+
+    /// Kernels before 5.13 report no `full` line for CPU
+    const CPU_PRESSURE_OLD: &str = "some avg10=12.34 avg60=5.00 avg300=1.10 total=55555555\n";
+
+    #[test]
+    fn parse_pressure_reads_some_and_full() {
+        let memory = parse_pressure(MEMORY_PRESSURE).unwrap();
+        assert!((memory.some.avg10 - 1.5).abs() < f64::EPSILON);
+        assert!((memory.some.avg300 - 0.25).abs() < f64::EPSILON);
+        assert_eq!(memory.some.total, 987_654);
+        let full = memory.full.unwrap();
+        assert!((full.avg60 - 0.25).abs() < f64::EPSILON);
+        assert_eq!(full.total, 123_456);
+    }
+
+    #[test]
+    fn parse_pressure_without_full() {
+        let cpu = parse_pressure(CPU_PRESSURE_OLD).unwrap();
+        assert!((cpu.some.avg10 - 12.34).abs() < f64::EPSILON);
+        assert_eq!(cpu.some.total, 55_555_555);
+        assert!(cpu.full.is_none());
+
+        assert!(parse_pressure("full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").is_none());
+        assert!(parse_pressure("some avg10=abc avg60=0.00 avg300=0.00 total=0\n").is_none());
+    }
+
+    #[test]
+    fn collect_reads_host_and_cgroup_pressure() {
+        let root = tempfile::tempdir().unwrap();
+        let host = root.path().join("pressure");
+        let cgroup = root.path().join("cgroup");
+        fs::create_dir_all(&host).unwrap();
+        fs::create_dir_all(&cgroup).unwrap();
+        fs::write(host.join("cpu"), CPU_PRESSURE_OLD).unwrap();
+        fs::write(host.join("memory"), MEMORY_PRESSURE).unwrap();
+        fs::write(cgroup.join("cpu.pressure"), MEMORY_PRESSURE).unwrap();
+        fs::write(cgroup.join("io.pressure"), CPU_PRESSURE_OLD).unwrap();
+
+        let (host_pressure, cgroup_pressure) =
+            PressureCollector::with_dirs(host, Some(cgroup)).collect();
+
+        assert!(host_pressure.cpu.unwrap().full.is_none());
+        assert!(host_pressure.memory.unwrap().full.is_some());
+        // No io file, as when booted with psi=0 for one resource
+        assert!(host_pressure.io.is_none());
+
+        let cgroup_pressure = cgroup_pressure.unwrap();
+        assert_eq!(cgroup_pressure.cpu.unwrap().some.total, 987_654);
+        assert!(cgroup_pressure.memory.is_none());
+        assert_eq!(cgroup_pressure.io.unwrap().some.total, 55_555_555);
+    }
+
+    #[test]
+    fn collect_without_psi() {
+        let root = tempfile::tempdir().unwrap();
+        let (pressure, cgroup_pressure) =
+            PressureCollector::with_dirs(root.path().join("missing"), None).collect();
+        assert!(pressure.cpu.is_none() && pressure.memory.is_none() && pressure.io.is_none());
+        assert!(cgroup_pressure.is_none());
+    }
+}
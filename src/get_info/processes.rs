@@ -0,0 +1,383 @@
+use crate::data_struct::{ProcessInfo, TopProcesses};
+use crate::get_info::Collector;
+use log::trace;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const PROC_ROOT: &str = "/proc";
+const PASSWD_PATH: &str = "/etc/passwd";
+const CMDLINE_MAX_LEN: usize = 256;
+
+/// The fields of /proc/[pid]/stat we care about
+#[derive(Debug, Clone)]
+pub struct PidStat {
+    pub name: String,
+    /// utime + stime in clock ticks
+    pub cpu_ticks: u64,
+    /// Distinguishes a reused pid from the process we saw last time
+    pub start_time: u64,
+    pub rss_pages: u64,
+}
+
+/// Parse /proc/[pid]/stat. The command name may itself contain spaces and parentheses,
+/// so fields are counted from the last `)`.
+#[must_use]
+pub fn parse_pid_stat(content: &str) -> Option<PidStat> {
+    let name_start = content.find('(')?;
+    let name_end = content.rfind(')')?;
+    let name = content.get(name_start + 1..name_end)?.to_string();
+
+    // Field 3 (state) is index 0 here
+    let fields: Vec<&str> = content.get(name_end + 1..)?.split_whitespace().collect();
+    let field = |i: usize| fields.get(i).and_then(|v| v.parse::<u64>().ok());
+
+    Some(PidStat {
+        name,
+        cpu_ticks: field(11)? + field(12)?,
+        start_time: field(19)?,
+        rss_pages: field(21)?,
+    })
+}
+
+/// Numeric entries of `proc_root`
+#[must_use]
+pub fn pids(proc_root: &Path) -> Vec<u32> {
+    let Ok(entries) = fs::read_dir(proc_root) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .collect()
+}
+
+/// Command line with NUL separators replaced by spaces; empty for kernel threads
+#[must_use]
+pub fn read_cmdline(proc_root: &Path, pid: u32) -> Option<String> {
+    let raw = fs::read(proc_root.join(pid.to_string()).join("cmdline")).ok()?;
+    let cmdline = String::from_utf8_lossy(&raw)
+        .split('\0')
+        .filter(|arg| !arg.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(cmdline)
+}
+
+fn read_uid(proc_root: &Path, pid: u32) -> Option<u32> {
+    let status = fs::read_to_string(proc_root.join(pid.to_string()).join("status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// uid -> user name from the content of /etc/passwd
+#[must_use]
+pub fn parse_passwd(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse::<u32>().ok()?;
+            Some((uid, name.to_string()))
+        })
+        .collect()
+}
+
+fn truncate(mut text: String, max_len: usize) -> String {
+    if text.len() > max_len {
+        let mut end = max_len;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...");
+    }
+    text
+}
+
+#[cfg(unix)]
+fn sysconf(name: libc::c_int, fallback: u64) -> u64 {
+    u64::try_from(unsafe { libc::sysconf(name) })
+        .ok()
+        .filter(|v| *v > 0)
+        .unwrap_or(fallback)
+}
+
+#[cfg(unix)]
+fn clock_ticks_and_page_size() -> (u64, u64) {
+    (
+        sysconf(libc::_SC_CLK_TCK, 100),
+        sysconf(libc::_SC_PAGESIZE, 4096),
+    )
+}
+
+#[cfg(not(unix))]
+fn clock_ticks_and_page_size() -> (u64, u64) {
+    (100, 4096)
+}
+
+/// Samples every process in /proc at most once per `interval` and keeps the
+/// busiest `count` by CPU and by resident memory.
+pub struct TopProcessCollector {
+    proc_root: PathBuf,
+    count: usize,
+    interval: Duration,
+    clock_ticks: u64,
+    page_size: u64,
+    /// pid -> (`start_time`, `cpu_ticks`) of the previous sample
+    last_ticks: HashMap<u32, (u64, u64)>,
+    last_sample: Instant,
+}
+
+impl TopProcessCollector {
+    #[must_use]
+    pub fn new(count: usize, interval: Duration) -> Self {
+        Self::with_proc_root(PathBuf::from(PROC_ROOT), count, interval)
+    }
+
+    #[must_use]
+    pub fn with_proc_root(proc_root: PathBuf, count: usize, interval: Duration) -> Self {
+        let (clock_ticks, page_size) = clock_ticks_and_page_size();
+        let mut collector = Self {
+            proc_root,
+            count,
+            interval,
+            clock_ticks,
+            page_size,
+            last_ticks: HashMap::new(),
+            last_sample: Instant::now(),
+        };
+        // Prime the CPU counters so the first snapshot has a baseline
+        collector.sample();
+        collector
+    }
+
+    fn sample(&mut self) -> Vec<(u32, PidStat, f64)> {
+        let elapsed_ticks = self.last_sample.elapsed().as_secs_f64() * self.clock_ticks as f64;
+        let mut processes = Vec::new();
+        let mut ticks = HashMap::new();
+
+        for pid in pids(&self.proc_root) {
+            let Some(stat) = fs::read_to_string(self.proc_root.join(pid.to_string()).join("stat"))
+                .ok()
+                .and_then(|content| parse_pid_stat(&content))
+            else {
+                continue;
+            };
+
+            // Percent of one core, like top
+            let cpu = match self.last_ticks.get(&pid) {
+                Some((start_time, prev_ticks))
+                    if *start_time == stat.start_time && elapsed_ticks > 0.0 =>
+                {
+                    stat.cpu_ticks.saturating_sub(*prev_ticks) as f64 / elapsed_ticks * 100.0
+                }
+                _ => 0.0,
+            };
+            ticks.insert(pid, (stat.start_time, stat.cpu_ticks));
+            processes.push((pid, stat, cpu));
+        }
+
+        self.last_ticks = ticks;
+        self.last_sample = Instant::now();
+        processes
+    }
+
+    fn process_info(
+        &self,
+        pid: u32,
+        stat: &PidStat,
+        cpu: f64,
+        users: &HashMap<u32, String>,
+    ) -> ProcessInfo {
+        let user = read_uid(&self.proc_root, pid).map_or_else(String::new, |uid| {
+            users.get(&uid).cloned().unwrap_or_else(|| uid.to_string())
+        });
+        ProcessInfo {
+            pid,
+            name: stat.name.clone(),
+            user,
+            cmdline: truncate(
+                read_cmdline(&self.proc_root, pid).unwrap_or_default(),
+                CMDLINE_MAX_LEN,
+            ),
+            cpu,
+            rss: stat.rss_pages * self.page_size,
+        }
+    }
+}
+
+impl Collector for TopProcessCollector {
+    /// `None` until `interval` has passed since the previous snapshot
+    type Output = Option<TopProcesses>;
+
+    fn collect(&mut self) -> Option<TopProcesses> {
+        if self.last_sample.elapsed() < self.interval {
+            return None;
+        }
+
+        let mut processes = self.sample();
+        let users = fs::read_to_string(PASSWD_PATH)
+            .map(|content| parse_passwd(&content))
+            .unwrap_or_default();
+
+        processes.sort_by(|a, b| b.2.total_cmp(&a.2));
+        let by_cpu = processes
+            .iter()
+            .take(self.count)
+            .map(|(pid, stat, cpu)| self.process_info(*pid, stat, *cpu, &users))
+            .collect();
+
+        processes.sort_by_key(|(_, stat, _)| std::cmp::Reverse(stat.rss_pages));
+        let by_memory = processes
+            .iter()
+            .take(self.count)
+            .map(|(pid, stat, cpu)| self.process_info(*pid, stat, *cpu, &users))
+            .collect();
+
+        let top = TopProcesses { by_cpu, by_memory };
+        trace!("REALTIME TOP PROCESSES successfully retrieved: {top:?}");
+        Some(top)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A /proc/[pid]/stat line; every field not under test is a placeholder
+    fn stat_line(
+        pid: u32,
+        comm: &str,
+        utime: u64,
+        stime: u64,
+        start_time: u64,
+        rss: u64,
+    ) -> String {
+        format!(
+            "{pid} ({comm}) S 1 {pid} {pid} 0 -1 4194560 1000 0 0 0 {utime} {stime} 0 0 20 0 1 0 \
+             {start_time} 123456789 {rss} 18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 0 17 3 0 0"
+        )
+    }
+
+    fn write_process(proc_root: &Path, pid: u32, stat: &str, uid: u32, cmdline: &[u8]) {
+        let dir = proc_root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("stat"), stat).unwrap();
+        fs::write(
+            dir.join("status"),
+            format!("Name:\tx\nUid:\t{uid}\t{uid}\t{uid}\t{uid}\n"),
+        )
+        .unwrap();
+        fs::write(dir.join("cmdline"), cmdline).unwrap();
+    }
+
+    #[test]
+    fn parse_pid_stat_handles_spaces_and_parentheses_in_the_name() {
+        let stat = parse_pid_stat(&stat_line(42, "tmux: server) (x", 150, 50, 9000, 2048)).unwrap();
+        assert_eq!(stat.name, "tmux: server) (x");
+        assert_eq!(stat.cpu_ticks, 200);
+        assert_eq!(stat.start_time, 9000);
+        assert_eq!(stat.rss_pages, 2048);
+
+        assert!(parse_pid_stat("42 (bash) S 1 42").is_none());
+        assert!(parse_pid_stat("").is_none());
+    }
+
+    #[test]
+    fn parse_passwd_maps_uids_to_names() {
+        let users = parse_passwd(
+            "root:x:0:0:root:/root:/bin/bash\n# comment\nnobody:x:65534:65534::/:/sbin/nologin\n",
+        );
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[&0], "root");
+        assert_eq!(users[&65534], "nobody");
+    }
+
+    #[test]
+    fn collect_ranks_processes_from_a_proc_fixture() {
+        let root = tempfile::tempdir().unwrap();
+        let proc_root = root.path();
+        let uid = 4_000_000;
+        write_process(
+            proc_root,
+            100,
+            &stat_line(100, "idle (worker)", 10, 0, 500, 10),
+            uid,
+            b"idle\0--flag\0",
+        );
+        write_process(
+            proc_root,
+            200,
+            &stat_line(200, "busy", 10, 0, 600, 5),
+            uid,
+            b"busy\0",
+        );
+        write_process(
+            proc_root,
+            300,
+            &stat_line(300, "reused", 10, 0, 700, 1),
+            uid,
+            b"",
+        );
+        fs::create_dir_all(proc_root.join("self")).unwrap();
+        fs::write(proc_root.join("uptime"), "1.0 1.0\n").unwrap();
+
+        let mut collector =
+            TopProcessCollector::with_proc_root(proc_root.to_path_buf(), 2, Duration::ZERO);
+
+        // 200 keeps running, 300 exited and its pid went to a new process
+        write_process(
+            proc_root,
+            100,
+            &stat_line(100, "idle (worker)", 11, 0, 500, 10),
+            uid,
+            b"idle\0--flag\0",
+        );
+        write_process(
+            proc_root,
+            200,
+            &stat_line(200, "busy", 40, 20, 600, 5),
+            uid,
+            b"busy\0",
+        );
+        write_process(
+            proc_root,
+            300,
+            &stat_line(300, "reused", 900, 0, 800, 1),
+            uid,
+            b"",
+        );
+        std::thread::sleep(Duration::from_millis(20));
+
+        let top = collector.collect().unwrap();
+        let by_cpu: Vec<u32> = top.by_cpu.iter().map(|p| p.pid).collect();
+        assert_eq!(by_cpu, [200, 100]);
+        assert!(top.by_cpu[0].cpu > top.by_cpu[1].cpu);
+        assert_eq!(top.by_cpu[1].cmdline, "idle --flag");
+        assert_eq!(top.by_cpu[1].name, "idle (worker)");
+        assert_eq!(top.by_cpu[1].user, uid.to_string());
+
+        let by_memory: Vec<u32> = top.by_memory.iter().map(|p| p.pid).collect();
+        assert_eq!(by_memory, [100, 200]);
+        assert_eq!(top.by_memory[0].rss, 10 * collector.page_size);
+    }
+
+    #[test]
+    fn collect_waits_for_the_interval() {
+        let root = tempfile::tempdir().unwrap();
+        let mut collector = TopProcessCollector::with_proc_root(
+            root.path().to_path_buf(),
+            5,
+            Duration::from_hours(1),
+        );
+        assert!(collector.collect().is_none());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::data_struct::{FanSensor, Sensors, TemperatureSensor};
+use crate::get_info::Collector;
+use log::trace;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+
+/// hwmon drivers whose temperatures belong to the CPU
+const CPU_CHIPS: &[&str] = &[
+    "coretemp",
+    "k10temp",
+    "k8temp",
+    "zenpower",
+    "fam15h_power",
+    "cpu_thermal",
+    "cpu-thermal",
+    "soc_thermal",
+    "via_cputemp",
+];
+
+/// thermal zone types that describe the CPU
+const CPU_THERMAL_ZONES: &[&str] = &[
+    "x86_pkg_temp",
+    "cpu_thermal",
+    "cpu-thermal",
+    "soc_thermal",
+    "cpu",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorKind {
+    Temperature,
+    Fan,
+}
+
+#[derive(Debug, Clone)]
+pub struct SensorSource {
+    pub chip: String,
+    pub label: String,
+    pub kind: SensorKind,
+    pub is_cpu: bool,
+    /// File holding the raw value: millidegrees Celsius or RPM
+    pub input: PathBuf,
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    Some(fs::read_to_string(path).ok()?.trim().to_string())
+}
+
+/// Discover temperature and fan inputs below `hwmon_root` (normally `/sys/class/hwmon`)
+#[must_use]
+pub fn hwmon_sensors(hwmon_root: &Path) -> Vec<SensorSource> {
+    let Ok(entries) = fs::read_dir(hwmon_root) else {
+        return Vec::new();
+    };
+
+    let mut hwmon_dirs: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    hwmon_dirs.sort();
+
+    let mut chips = Vec::new();
+    for hwmon_dir in hwmon_dirs {
+        // Older kernels keep the attributes in the device directory
+        let attr_dir = if hwmon_dir.join("name").exists() {
+            hwmon_dir.clone()
+        } else {
+            hwmon_dir.join("device")
+        };
+        let Some(name) = read_trimmed(&attr_dir.join("name")) else {
+            continue;
+        };
+        let device = fs::read_link(hwmon_dir.join("device"))
+            .ok()
+            .and_then(|link| Some(link.file_name()?.to_string_lossy().into_owned()));
+        chips.push((name, device, attr_dir));
+    }
+
+    // Several NVMe drives all call themselves "nvme"; tell them apart by device
+    let mut name_counts: HashMap<&str, usize> = HashMap::new();
+    for (name, _, _) in &chips {
+        *name_counts.entry(name.as_str()).or_default() += 1;
+    }
+
+    let mut sensors = Vec::new();
+    for (name, device, attr_dir) in &chips {
+        let chip = match device {
+            Some(device) if name_counts[name.as_str()] > 1 => format!("{name} {device}"),
+            _ => name.clone(),
+        };
+        let is_cpu = CPU_CHIPS.contains(&name.as_str());
+
+        let Ok(files) = fs::read_dir(attr_dir) else {
+            continue;
+        };
+        let mut inputs: Vec<(SensorKind, String)> = files
+            .flatten()
+            .filter_map(|file| {
+                let file_name = file.file_name().to_str()?.to_string();
+                let prefix = file_name.strip_suffix("_input")?;
+                if prefix.starts_with("temp") {
+                    Some((SensorKind::Temperature, prefix.to_string()))
+                } else if prefix.starts_with("fan") {
+                    Some((SensorKind::Fan, prefix.to_string()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        inputs.sort_by_key(|(_, prefix)| natural_key(prefix));
+
+        for (kind, prefix) in inputs {
+            let label = read_trimmed(&attr_dir.join(format!("{prefix}_label")))
+                .filter(|label| !label.is_empty())
+                .unwrap_or_else(|| prefix.clone());
+            sensors.push(SensorSource {
+                chip: chip.clone(),
+                label,
+                kind,
+                is_cpu,
+                input: attr_dir.join(format!("{prefix}_input")),
+            });
+        }
+    }
+
+    sensors
+}
+
+/// `temp10` sorts after `temp2`
+fn natural_key(prefix: &str) -> (String, u32) {
+    let digits = prefix.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let stem = &prefix[..prefix.len() - digits.len()];
+    (stem.to_string(), digits.parse().unwrap_or(0))
+}
+
+/// Discover thermal zones below `thermal_root` (normally `/sys/class/thermal`)
+#[must_use]
+pub fn thermal_zone_sensors(thermal_root: &Path) -> Vec<SensorSource> {
+    let Ok(entries) = fs::read_dir(thermal_root) else {
+        return Vec::new();
+    };
+
+    let mut zones: Vec<(u32, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let index = file_name
+                .to_str()?
+                .strip_prefix("thermal_zone")?
+                .parse::<u32>()
+                .ok()?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    zones.sort_by_key(|(index, _)| *index);
+
+    zones
+        .into_iter()
+        .filter_map(|(index, zone_dir)| {
+            let zone_type = read_trimmed(&zone_dir.join("type"))?;
+            Some(SensorSource {
+                chip: format!("thermal_zone{index}"),
+                is_cpu: CPU_THERMAL_ZONES.contains(&zone_type.as_str()),
+                label: zone_type,
+                kind: SensorKind::Temperature,
+                input: zone_dir.join("temp"),
+            })
+        })
+        .collect()
+}
+
+pub struct SensorCollector {
+    sources: Vec<SensorSource>,
+}
+
+impl SensorCollector {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_roots(Path::new(HWMON_ROOT), Path::new(THERMAL_ROOT))
+    }
+
+    #[must_use]
+    pub fn with_roots(hwmon_root: &Path, thermal_root: &Path) -> Self {
+        let mut sources = hwmon_sensors(hwmon_root);
+        // Thermal zones are usually mirrored in hwmon; only use them when hwmon has nothing
+        if !sources.iter().any(|s| s.kind == SensorKind::Temperature) {
+            sources.extend(thermal_zone_sensors(thermal_root));
+        }
+        trace!("SENSORS discovered: {sources:?}");
+        Self { sources }
+    }
+
+    #[must_use]
+    pub fn sources(&self) -> &[SensorSource] {
+        &self.sources
+    }
+}
+
+impl Default for SensorCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Collector for SensorCollector {
+    type Output = Sensors;
+
+    fn collect(&mut self) -> Sensors {
+        let mut sensors = Sensors {
+            cpu_temperature: None,
+            temperatures: Vec::new(),
+            fans: Vec::new(),
+        };
+
+        for source in &self.sources {
+            let Some(raw) = read_trimmed(&source.input).and_then(|v| v.parse::<i64>().ok()) else {
+                continue;
+            };
+            match source.kind {
+                SensorKind::Temperature => {
+                    let temperature = raw as f64 / 1000.0;
+                    if source.is_cpu {
+                        sensors.cpu_temperature = Some(
+                            sensors
+                                .cpu_temperature
+                                .map_or(temperature, |hottest| hottest.max(temperature)),
+                        );
+                    }
+                    sensors.temperatures.push(TemperatureSensor {
+                        chip: source.chip.clone(),
+                        label: source.label.clone(),
+                        temperature,
+                    });
+                }
+                SensorKind::Fan => sensors.fans.push(FanSensor {
+                    chip: source.chip.clone(),
+                    label: source.label.clone(),
+                    rpm: u64::try_from(raw).unwrap_or(0),
+                }),
+            }
+        }
+
+        trace!("REALTIME SENSORS successfully retrieved: {sensors:?}");
+        sensors
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// `hwmon0` is a coretemp chip with labelled inputs, `hwmon1` and `hwmon2`
+    /// are two `NVMe` drives, `hwmon3` is a fan controller on an older kernel
+    /// that keeps its attributes in the device directory
+    fn hwmon_fixture() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let hwmon = root.path().join("hwmon");
+
+        let coretemp = hwmon.join("hwmon0");
+        write(&coretemp.join("name"), "coretemp\n");
+        write(&coretemp.join("temp1_input"), "52000\n");
+        write(&coretemp.join("temp1_label"), "Package id 0\n");
+        write(&coretemp.join("temp2_input"), "49000\n");
+        write(&coretemp.join("temp2_label"), "Core 0\n");
+        write(&coretemp.join("temp10_input"), "61500\n");
+        write(&coretemp.join("temp10_label"), "\n");
+        write(&coretemp.join("temp1_crit"), "100000\n");
+
+        for (hwmon_dir, device, temp) in [
+            ("hwmon1", "nvme0", "38850\n"),
+            ("hwmon2", "nvme1", "41850\n"),
+        ] {
+            let dir = hwmon.join(hwmon_dir);
+            write(&dir.join("name"), "nvme\n");
+            write(&dir.join("temp1_input"), temp);
+            write(&dir.join("temp1_label"), "Composite\n");
+            let device_dir = root.path().join("devices").join(device);
+            fs::create_dir_all(&device_dir).unwrap();
+            symlink(&device_dir, dir.join("device")).unwrap();
+        }
+
+        let it87 = hwmon.join("hwmon3/device");
+        write(&it87.join("name"), "it8728\n");
+        write(&it87.join("fan1_input"), "1250\n");
+        write(&it87.join("fan2_input"), "-1\n");
+        root
+    }
+
+    #[test]
+    fn hwmon_sensors_reads_labels_and_tells_identical_chips_apart() {
+        let root = hwmon_fixture();
+        let sources = hwmon_sensors(&root.path().join("hwmon"));
+
+        let names: Vec<(&str, &str, SensorKind, bool)> = sources
+            .iter()
+            .map(|s| (s.chip.as_str(), s.label.as_str(), s.kind, s.is_cpu))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("coretemp", "Package id 0", SensorKind::Temperature, true),
+                ("coretemp", "Core 0", SensorKind::Temperature, true),
+                ("coretemp", "temp10", SensorKind::Temperature, true),
+                ("nvme nvme0", "Composite", SensorKind::Temperature, false),
+                ("nvme nvme1", "Composite", SensorKind::Temperature, false),
+                ("it8728", "fan1", SensorKind::Fan, false),
+                ("it8728", "fan2", SensorKind::Fan, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_reports_the_hottest_cpu_sensor_and_fan_speeds() {
+        let root = hwmon_fixture();
+        let mut collector =
+            SensorCollector::with_roots(&root.path().join("hwmon"), &root.path().join("thermal"));
+
+        let sensors = collector.collect();
+        assert_eq!(sensors.cpu_temperature, Some(61.5));
+        assert_eq!(sensors.temperatures.len(), 5);
+        assert!((sensors.temperatures[3].temperature - 38.85).abs() < 1e-9);
+        let fans: Vec<u64> = sensors.fans.iter().map(|fan| fan.rpm).collect();
+        assert_eq!(fans, [1250, 0]);
+    }
+
+    #[test]
+    fn thermal_zones_are_used_only_without_hwmon_temperatures() {
+        let root = tempfile::tempdir().unwrap();
+        let thermal = root.path().join("thermal");
+        write(&thermal.join("thermal_zone0/type"), "acpitz\n");
+        write(&thermal.join("thermal_zone0/temp"), "27800\n");
+        write(&thermal.join("thermal_zone1/type"), "x86_pkg_temp\n");
+        write(&thermal.join("thermal_zone1/temp"), "45000\n");
+        write(&thermal.join("cooling_device0/type"), "Processor\n");
+
+        let mut collector = SensorCollector::with_roots(&root.path().join("hwmon"), &thermal);
+        let sensors = collector.collect();
+        assert_eq!(sensors.cpu_temperature, Some(45.0));
+        assert_eq!(sensors.temperatures.len(), 2);
+        assert_eq!(sensors.temperatures[0].chip, "thermal_zone0");
+        assert_eq!(sensors.temperatures[0].label, "acpitz");
+
+        let hwmon = hwmon_fixture();
+        let collector = SensorCollector::with_roots(&hwmon.path().join("hwmon"), &thermal);
+        assert!(
+            collector
+                .sources()
+                .iter()
+                .all(|s| !s.chip.starts_with("thermal_zone"))
+        );
+    }
+}
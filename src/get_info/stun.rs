@@ -0,0 +1,325 @@
+use crate::utils::random_u64;
+use log::trace;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{UdpSocket, lookup_host};
+use tokio::time::timeout;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+const DEFAULT_PORT: u16 = 3478;
+/// Requests are retransmitted with a doubling timeout, as RFC 5389 section 7.2.1 suggests
+const ATTEMPTS: u32 = 3;
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[must_use]
+pub fn transaction_id() -> [u8; 12] {
+    let mut id = [0u8; 12];
+    for chunk in id.chunks_mut(4) {
+        chunk.copy_from_slice(&random_u64().to_ne_bytes()[..4]);
+    }
+    id
+}
+
+/// A Binding Request without attributes
+#[must_use]
+pub fn binding_request(transaction_id: &[u8; 12]) -> [u8; HEADER_LEN] {
+    let mut request = [0u8; HEADER_LEN];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    // Message length stays 0
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..20].copy_from_slice(transaction_id);
+    request
+}
+
+/// Decode a (XOR-)MAPPED-ADDRESS value. For the XOR variant the address is
+/// masked with the magic cookie, followed by the transaction id for IPv6.
+fn parse_address(value: &[u8], xor_mask: Option<&[u8; 16]>) -> Option<IpAddr> {
+    let family = *value.get(1)?;
+    let mask = |i: usize, byte: u8| xor_mask.map_or(byte, |mask| byte ^ mask[i]);
+    match family {
+        0x01 => {
+            let raw = value.get(4..8)?;
+            Some(IpAddr::V4(Ipv4Addr::new(
+                mask(0, raw[0]),
+                mask(1, raw[1]),
+                mask(2, raw[2]),
+                mask(3, raw[3]),
+            )))
+        }
+        0x02 => {
+            let raw = value.get(4..20)?;
+            let mut octets = [0u8; 16];
+            for (i, octet) in octets.iter_mut().enumerate() {
+                *octet = mask(i, raw[i]);
+            }
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+/// The mapped address in a Binding Success Response to `transaction_id`.
+/// XOR-MAPPED-ADDRESS is preferred; MAPPED-ADDRESS is accepted from RFC 3489 servers.
+#[must_use]
+pub fn parse_binding_response(response: &[u8], transaction_id: &[u8; 12]) -> Option<IpAddr> {
+    let header = response.get(..HEADER_LEN)?;
+    if u16::from_be_bytes([header[0], header[1]]) != BINDING_SUCCESS
+        || header[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || header[8..20] != transaction_id[..]
+    {
+        return None;
+    }
+    let length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let attributes = response.get(HEADER_LEN..HEADER_LEN + length)?;
+
+    let mut xor_mask = [0u8; 16];
+    xor_mask[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    xor_mask[4..].copy_from_slice(transaction_id);
+
+    let mut mapped = None;
+    let mut offset = 0;
+    while let Some(attribute) = attributes.get(offset..offset + 4) {
+        let kind = u16::from_be_bytes([attribute[0], attribute[1]]);
+        let length = usize::from(u16::from_be_bytes([attribute[2], attribute[3]]));
+        let value = attributes.get(offset + 4..offset + 4 + length)?;
+        match kind {
+            ATTR_XOR_MAPPED_ADDRESS => return parse_address(value, Some(&xor_mask)),
+            ATTR_MAPPED_ADDRESS => mapped = parse_address(value, None),
+            _ => {}
+        }
+        // Values are padded to a multiple of 4 bytes
+        offset += 4 + length.div_ceil(4) * 4;
+    }
+    mapped
+}
+
+/// `host`, `host:port` or `[v6]:port`; the port defaults to 3478
+fn with_default_port(server: &str) -> String {
+    let has_port = match server.rsplit_once(':') {
+        Some((host, port)) => {
+            port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']'))
+        }
+        None => false,
+    };
+    if has_port {
+        server.to_string()
+    } else if server.contains(':') && !server.starts_with('[') {
+        format!("[{server}]:{DEFAULT_PORT}")
+    } else {
+        format!("{server}:{DEFAULT_PORT}")
+    }
+}
+
+/// Ask one STUN server for our public address over IPv4 or IPv6
+pub async fn stun_query(server: &str, ipv6: bool) -> Option<IpAddr> {
+    let server_addr = lookup_host(with_default_port(server))
+        .await
+        .ok()?
+        .find(|addr| addr.is_ipv6() == ipv6)?;
+    let bind_addr: SocketAddr = if ipv6 {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr).await.ok()?;
+    socket.connect(server_addr).await.ok()?;
+
+    let transaction_id = transaction_id();
+    let request = binding_request(&transaction_id);
+    let mut buf = [0u8; 576];
+    let mut wait = INITIAL_TIMEOUT;
+    for _ in 0..ATTEMPTS {
+        socket.send(&request).await.ok()?;
+        // Stray or malformed datagrams are ignored until the timeout
+        let received = timeout(wait, async {
+            loop {
+                let len = socket.recv(&mut buf).await.ok()?;
+                if let Some(addr) = parse_binding_response(&buf[..len], &transaction_id) {
+                    return Some(addr);
+                }
+            }
+        })
+        .await;
+        if let Ok(addr) = received {
+            trace!("STUN {server} ({server_addr}) mapped address: {addr:?}");
+            return addr;
+        }
+        wait *= 2;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: [u8; 12] = *b"komari-agent";
+
+    fn attribute(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut attribute = kind.to_be_bytes().to_vec();
+        attribute.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        attribute.extend_from_slice(value);
+        attribute.resize(attribute.len().div_ceil(4) * 4, 0);
+        attribute
+    }
+
+    fn response(transaction_id: &[u8; 12], attributes: &[Vec<u8>]) -> Vec<u8> {
+        let body = attributes.concat();
+        let mut response = BINDING_SUCCESS.to_be_bytes().to_vec();
+        response.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        response.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        response.extend_from_slice(transaction_id);
+        response.extend_from_slice(&body);
+        response
+    }
+
+    /// A (XOR-)MAPPED-ADDRESS value for `ip`, masked the way `parse_address` unmasks it
+    fn address(ip: IpAddr, transaction_id: &[u8; 12], xor: bool) -> Vec<u8> {
+        let mut mask = MAGIC_COOKIE.to_be_bytes().to_vec();
+        mask.extend_from_slice(transaction_id);
+        let (family, octets) = match ip {
+            IpAddr::V4(ip) => (0x01, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (0x02, ip.octets().to_vec()),
+        };
+        let mut value = vec![0, family, 0x12, 0x34];
+        value.extend(
+            octets
+                .iter()
+                .zip(&mask)
+                .map(|(octet, mask)| if xor { octet ^ mask } else { *octet }),
+        );
+        value
+    }
+
+    #[test]
+    fn binding_request_header() {
+        let request = binding_request(&ID);
+        assert_eq!(
+            request[..8],
+            [0x00, 0x01, 0x00, 0x00, 0x21, 0x12, 0xA4, 0x42]
+        );
+        assert_eq!(request[8..], ID);
+    }
+
+    #[test]
+    fn transaction_ids_differ() {
+        assert_ne!(transaction_id(), transaction_id());
+    }
+
+    #[test]
+    fn parses_xor_mapped_addresses() {
+        for ip in ["203.0.113.7", "2001:db8::1234:5678"] {
+            let ip: IpAddr = ip.parse().unwrap();
+            let response = response(
+                &ID,
+                &[
+                    attribute(
+                        ATTR_MAPPED_ADDRESS,
+                        &address("192.0.2.1".parse().unwrap(), &ID, false),
+                    ),
+                    attribute(ATTR_XOR_MAPPED_ADDRESS, &address(ip, &ID, true)),
+                ],
+            );
+            assert_eq!(parse_binding_response(&response, &ID), Some(ip));
+        }
+    }
+
+    #[test]
+    fn falls_back_to_mapped_address() {
+        let ip: IpAddr = "198.51.100.20".parse().unwrap();
+        let response = response(
+            &ID,
+            &[
+                // SOFTWARE, with a length that needs padding
+                attribute(0x8022, b"rfc3489"),
+                attribute(ATTR_MAPPED_ADDRESS, &address(ip, &ID, false)),
+            ],
+        );
+        assert_eq!(parse_binding_response(&response, &ID), Some(ip));
+    }
+
+    #[test]
+    fn rejects_other_transactions_and_truncated_messages() {
+        let value = address("203.0.113.7".parse().unwrap(), &ID, true);
+        let valid = response(&ID, &[attribute(ATTR_XOR_MAPPED_ADDRESS, &value)]);
+        assert!(parse_binding_response(&valid, &ID).is_some());
+
+        let other = response(
+            b"other-transa",
+            &[attribute(ATTR_XOR_MAPPED_ADDRESS, &value)],
+        );
+        assert_eq!(parse_binding_response(&other, &ID), None);
+
+        // The message length claims more than was received
+        assert_eq!(parse_binding_response(&valid[..valid.len() - 2], &ID), None);
+
+        // The attribute length runs past the end of the message
+        let mut truncated = attribute(ATTR_XOR_MAPPED_ADDRESS, &value);
+        truncated[3] = 32;
+        let truncated = response(&ID, &[truncated]);
+        assert_eq!(parse_binding_response(&truncated, &ID), None);
+
+        // Too short for the address it claims to hold
+        let short = response(&ID, &[attribute(ATTR_XOR_MAPPED_ADDRESS, &value[..6])]);
+        assert_eq!(parse_binding_response(&short, &ID), None);
+    }
+
+    #[test]
+    fn adds_the_default_port() {
+        assert_eq!(
+            with_default_port("stun.example.com"),
+            "stun.example.com:3478"
+        );
+        assert_eq!(
+            with_default_port("stun.example.com:19302"),
+            "stun.example.com:19302"
+        );
+        assert_eq!(with_default_port("2001:db8::1"), "[2001:db8::1]:3478");
+        assert_eq!(
+            with_default_port("[2001:db8::1]:3479"),
+            "[2001:db8::1]:3479"
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_a_local_responder() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 576];
+            let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(len, HEADER_LEN);
+            let id: [u8; 12] = buf[8..20].try_into().unwrap();
+            // A stray datagram first, which the client has to skip
+            let stray = response(b"other-transa", &[]);
+            server.send_to(&stray, peer).await.unwrap();
+            let reply = response(
+                &id,
+                &[attribute(
+                    ATTR_XOR_MAPPED_ADDRESS,
+                    &address(peer.ip(), &id, true),
+                )],
+            );
+            server.send_to(&reply, peer).await.unwrap();
+        });
+
+        let mapped = stun_query(&server_addr.to_string(), false).await;
+        assert_eq!(mapped, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+
+    #[tokio::test]
+    async fn gives_up_without_an_answer() {
+        // Bound but never answering
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap().to_string();
+        assert_eq!(stun_query(&server_addr, false).await, None);
+        assert_eq!(stun_query(&server_addr, true).await, None);
+    }
+}
@@ -0,0 +1,28 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+//! Komari Monitor Agent in Rust.
+//!
+//! The collectors behind the agent are usable on their own: build a
+//! [`data_struct::BasicInfo`] once, keep a [`get_info::RealTimeCollectors`]
+//! around and call [`data_struct::RealTimeInfo::build`] on every tick, or drive
+//! any individual [`get_info::Collector`] directly.
+
+pub mod callbacks;
+pub mod config;
+pub mod data_struct;
+pub mod dry_run;
+pub mod get_info;
+pub mod offline_buffer;
+pub mod proxy;
+mod rustls_config;
+pub mod utils;
+pub mod watchdog;
+
+pub use get_info::Collector;
@@ -8,39 +8,90 @@
     clippy::too_many_lines
 )]
 
-use crate::callbacks::handle_callbacks;
 use crate::command_parser::Args;
-use crate::config::{ConfigPath, ConfigReader};
-use crate::data_struct::{BasicInfo, RealTimeInfo};
-use crate::dry_run::dry_run;
-use crate::get_info::network::network_saver::network_saver;
-use crate::utils::{build_urls, connect_ws, init_logger};
 use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
+use komari_monitor_rs::callbacks::handle_callbacks;
+use komari_monitor_rs::config::{ConfigPath, ConfigReader, UserConfig};
+use komari_monitor_rs::data_struct::{BasicInfo, RealTimeInfo};
+use komari_monitor_rs::dry_run::dry_run;
+use komari_monitor_rs::get_info::RealTimeCollectors;
+use komari_monitor_rs::get_info::gpu::{GpuCollector, gpu_name};
+use komari_monitor_rs::get_info::hardware::HardwareWatcher;
+use komari_monitor_rs::get_info::ip::{IpDetector, IpWatcher};
+use komari_monitor_rs::get_info::network::network_saver::network_saver;
+use komari_monitor_rs::offline_buffer::{OfflineBuffer, sample_timestamp};
+use komari_monitor_rs::proxy::OutboundProxy;
+use komari_monitor_rs::utils::{ReconnectBackoff, build_urls, connect_ws, init_logger};
 use log::{debug, error, info};
 use miniserde::json;
+use std::path::Path;
+use std::pin::pin;
 use std::process::exit;
 use std::sync::Arc;
 use std::time::Duration;
-use sysinfo::{CpuRefreshKind, DiskRefreshKind, Disks, MemoryRefreshKind, Networks, RefreshKind};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::time::sleep;
-use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+use tokio::time::{Instant, sleep, sleep_until};
+use tokio_tungstenite::tungstenite::{Error, Message, Utf8Bytes};
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 
-mod callbacks;
 mod command_parser;
-mod config;
-mod data_struct;
-mod dry_run;
-mod get_info;
-mod rustls_config;
-mod utils;
 
-#[tokio::main]
-async fn main() {
+/// Buffered samples sent after each live one, so a long outage drains
+/// without holding back fresh data
+const REPLAY_BATCH: usize = 60;
+
+type WsWriter = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+/// Drive `task` to completion while disconnected, still sampling into the
+/// offline buffer when it is enabled. Samples follow `next_sample`, the same
+/// schedule the live loop uses, so slow connection attempts leave no gaps.
+async fn sample_offline<F: Future>(
+    task: F,
+    collectors: &mut RealTimeCollectors,
+    buffer: Option<&mut OfflineBuffer>,
+    next_sample: &mut Instant,
+    config: &UserConfig,
+) -> F::Output {
+    let Some(buffer) = buffer else {
+        return task.await;
+    };
+    let mut task = pin!(task);
+    loop {
+        tokio::select! {
+            output = &mut task => return output,
+            () = sleep_until(*next_sample) => {
+                *next_sample = Instant::now() + Duration::from_millis(config.realtime_info_interval);
+                let mut real_time = RealTimeInfo::build(collectors, config.fake);
+                real_time.updated_at = Some(sample_timestamp());
+                buffer.push(json::to_string(&real_time));
+            }
+        }
+    }
+}
+
+/// Send up to `REPLAY_BATCH` buffered samples, oldest first
+async fn replay_offline(
+    write: &mut WsWriter,
+    buffer: Option<&mut OfflineBuffer>,
+) -> Result<(), Error> {
+    let Some(buffer) = buffer else {
+        return Ok(());
+    };
+    for _ in 0..REPLAY_BATCH {
+        let Some(sample) = buffer.peek() else {
+            break;
+        };
+        write.send(Message::Text(Utf8Bytes::from(sample))).await?;
+        buffer.pop();
+    }
+    Ok(())
+}
+
+fn main() {
     let args = Args::par();
 
     // Load configuration file
@@ -60,12 +111,21 @@ async fn main() {
     // Initialize logger with config
     init_logger(&config.log_level);
 
-    // Set global DURATION variable
-    unsafe {
-        crate::get_info::network::DURATION = config.realtime_info_interval as f64;
+    if let Some(proxy) = OutboundProxy::from_config(&config) {
+        // SAFETY: the tokio runtime and its worker threads are only started by `run`
+        #[cfg(feature = "nyquest-support")]
+        unsafe {
+            komari_monitor_rs::proxy::export_env(&proxy);
+        }
+        komari_monitor_rs::proxy::init(proxy);
     }
 
-    dry_run().await;
+    run(&args, &config_path, &config);
+}
+
+#[tokio::main]
+async fn run(args: &Args, config_path: &Path, config: &UserConfig) {
+    dry_run(config).await;
 
     if args.dry_run {
         exit(0);
@@ -75,6 +135,13 @@ async fn main() {
     debug!("HTTP Server: {}", config.http_server);
     debug!("Token: [REDACTED]");
     debug!("TLS: {}", config.tls);
+    debug!(
+        "Proxy: {}",
+        config.proxy.as_ref().map_or("None".to_string(), |proxy| format!(
+            "{:?} {}:{}",
+            proxy.scheme, proxy.host, proxy.port
+        ))
+    );
     debug!("Network Statistics: {}", !config.disable_network_statistics);
 
     let connection_urls = build_urls(
@@ -119,12 +186,12 @@ async fn main() {
         }
     }
 
-    let (network_saver_tx, mut network_saver_rx): (Sender<(u64, u64)>, Receiver<(u64, u64)>) =
+    let (network_saver_tx, network_saver_rx): (Sender<(u64, u64)>, Receiver<(u64, u64)>) =
         tokio::sync::mpsc::channel(15);
 
     if !config.disable_network_statistics {
         let config_clone = config.clone();
-        let config_path_clone = config_path.clone();
+        let config_path_clone = config_path.to_path_buf();
         let _listener = tokio::spawn(async move {
             network_saver(network_saver_tx, &config_clone, &config_path_clone).await;
         });
@@ -134,24 +201,74 @@ async fn main() {
         );
     }
 
+    let mut collectors = RealTimeCollectors::new(
+        config,
+        if config.disable_network_statistics {
+            None
+        } else {
+            Some(network_saver_rx)
+        },
+    );
+
+    let mut ip_watcher = (config.ip_check_interval > 0).then(|| {
+        IpWatcher::new(
+            IpDetector::from_config(config),
+            Duration::from_secs(config.ip_check_interval),
+        )
+    });
+
+    let mut hardware_watcher = (config.hardware_check_interval > 0)
+        .then(|| HardwareWatcher::new(Duration::from_secs(config.hardware_check_interval)));
+
+    let mut offline_buffer = OfflineBuffer::from_config(config);
+    let mut next_sample = Instant::now();
+
+    let mut backoff = ReconnectBackoff::new(
+        Duration::from_secs(config.reconnect_backoff_min),
+        Duration::from_secs(config.reconnect_backoff_max),
+        Duration::from_secs(config.reconnect_backoff_reset),
+    );
+
     loop {
-        let Ok(ws_stream) = connect_ws(
-            &connection_urls.ws_real_time,
-            config.tls,
-            config.ignore_unsafe_cert,
+        let Ok(ws_stream) = sample_offline(
+            Box::pin(connect_ws(
+                &connection_urls.ws_real_time,
+                config.tls,
+                config.ignore_unsafe_cert,
+            )),
+            &mut collectors,
+            offline_buffer.as_mut(),
+            &mut next_sample,
+            config,
         )
         .await
         else {
-            error!("Failed to connect to WebSocket server, retrying in 5 seconds");
-            sleep(Duration::from_secs(5)).await;
+            let (attempt, delay) = backoff.next_delay();
+            error!(
+                "Failed to connect to WebSocket server (attempt {attempt}), retrying in {:.1} seconds",
+                delay.as_secs_f64()
+            );
+            sample_offline(
+                sleep(delay),
+                &mut collectors,
+                offline_buffer.as_mut(),
+                &mut next_sample,
+                config,
+            )
+            .await;
             continue;
         };
+        backoff.connected();
+        if offline_buffer
+            .as_ref()
+            .is_some_and(|buffer| !buffer.is_empty())
+        {
+            info!("Replaying RealTime Info buffered while disconnected");
+        }
 
         let (write, mut read) = ws_stream.split();
 
-        let locked_write: Arc<
-            Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>,
-        > = Arc::new(Mutex::new(write));
+        let locked_write: Arc<Mutex<WsWriter>> = Arc::new(Mutex::new(write));
 
         // Handle callbacks
         {
@@ -170,8 +287,6 @@ async fn main() {
         }
 
         let mut sysinfo_sys = sysinfo::System::new();
-        let mut networks = Networks::new_with_refreshed_list();
-        let mut disks = Disks::new();
         sysinfo_sys.refresh_cpu_list(
             CpuRefreshKind::nothing()
                 .without_cpu_usage()
@@ -179,48 +294,80 @@ async fn main() {
         );
         sysinfo_sys.refresh_memory_specifics(MemoryRefreshKind::everything());
 
-        let basic_info = BasicInfo::build(&sysinfo_sys, config.fake, &config.ip_provider).await;
+        let gpu_name = collectors
+            .gpu
+            .as_ref()
+            .map_or_else(gpu_name, GpuCollector::name);
+        let mut basic_info = sample_offline(
+            BasicInfo::build(&sysinfo_sys, config, gpu_name),
+            &mut collectors,
+            offline_buffer.as_mut(),
+            &mut next_sample,
+            config,
+        )
+        .await;
 
         basic_info.push(connection_urls.basic_info.clone(), config.ignore_unsafe_cert);
 
         loop {
-            let start_time = tokio::time::Instant::now();
-            sysinfo_sys.refresh_specifics(
-                RefreshKind::nothing()
-                    .with_cpu(CpuRefreshKind::everything().without_frequency())
-                    .with_memory(MemoryRefreshKind::everything()),
-            );
-            networks.refresh(true);
-            disks.refresh_specifics(true, DiskRefreshKind::nothing().with_storage());
-            let real_time = RealTimeInfo::build(
-                &sysinfo_sys,
-                &networks,
-                if config.disable_network_statistics {
-                    None
-                } else {
-                    Some(&mut network_saver_rx)
-                },
-                &disks,
-                config.fake,
-            );
+            sleep_until(next_sample).await;
+            next_sample = Instant::now() + Duration::from_millis(config.realtime_info_interval);
+            let mut real_time = RealTimeInfo::build(&mut collectors, config.fake);
+            if offline_buffer.is_some() {
+                real_time.updated_at = Some(sample_timestamp());
+            }
 
             let json = json::to_string(&real_time);
             {
                 let mut write = locked_write.lock().await;
-                if let Err(e) = write.send(Message::Text(Utf8Bytes::from(json))).await {
+                if let Err(e) = write
+                    .send(Message::Text(Utf8Bytes::from(json.clone())))
+                    .await
+                {
                     error!(
                         "Error occurred while pushing RealTime Info, attempting to reconnect: {e}"
                     );
+                    if let Some(buffer) = offline_buffer.as_mut() {
+                        buffer.push(json);
+                    }
+                    break;
+                }
+                if let Err(e) = replay_offline(&mut write, offline_buffer.as_mut()).await {
+                    error!(
+                        "Error occurred while replaying buffered RealTime Info, attempting to reconnect: {e}"
+                    );
                     break;
                 }
             }
-            let end_time = start_time.elapsed();
 
-            sleep(Duration::from_millis({
-                let end = u64::try_from(end_time.as_millis()).unwrap_or(0);
-                config.realtime_info_interval.saturating_sub(end)
-            }))
-            .await;
+            let ip_changed = ip_watcher
+                .as_mut()
+                .and_then(IpWatcher::poll)
+                .is_some_and(|ip| basic_info.update_ip(&ip));
+            let hardware_changed = hardware_watcher
+                .as_mut()
+                .and_then(HardwareWatcher::poll)
+                .is_some_and(|mut hardware| {
+                    hardware.scale(config.fake);
+                    basic_info.update_hardware(hardware)
+                });
+            if ip_changed || hardware_changed {
+                basic_info.push(connection_urls.basic_info.clone(), config.ignore_unsafe_cert);
+            }
         }
+
+        let (attempt, delay) = backoff.next_delay();
+        info!(
+            "Reconnecting to WebSocket server (attempt {attempt}) in {:.1} seconds",
+            delay.as_secs_f64()
+        );
+        sample_offline(
+            sleep(delay),
+            &mut collectors,
+            offline_buffer.as_mut(),
+            &mut next_sample,
+            config,
+        )
+        .await;
     }
 }
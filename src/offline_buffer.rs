@@ -0,0 +1,333 @@
+use crate::config::UserConfig;
+use log::{debug, info, warn};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// Time of a sample as sent in `RealTimeInfo::updated_at`
+#[must_use]
+pub fn sample_timestamp() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+/// Serialized realtime samples taken while the server was unreachable.
+///
+/// The newest samples stay in memory within `max_samples` and `max_memory`
+/// bytes. Older ones are appended to the spill file as JSON lines when one is
+/// configured, up to `max_spill` bytes, and dropped otherwise. Replay starts
+/// with the spill file, so samples go out in the order they were taken. How
+/// far replay got is kept next to it in `<spill file>.offset`, so a restart
+/// during replay does not send the same samples again.
+pub struct OfflineBuffer {
+    samples: VecDeque<String>,
+    memory: usize,
+    max_samples: usize,
+    max_memory: usize,
+    spill_path: Option<PathBuf>,
+    max_spill: u64,
+    /// Bytes written to the spill file and the position replay has reached
+    spill_len: u64,
+    spill_offset: u64,
+    /// Kept open while the spill file is in use; the reader sits at `spill_offset`
+    /// plus the length of `next_spilled`
+    writer: Option<File>,
+    reader: Option<BufReader<File>>,
+    /// The spilled line returned by `peek` and not yet confirmed by `pop`
+    next_spilled: Option<String>,
+    dropped: u64,
+}
+
+/// `<spill file>.offset`
+fn offset_path(spill_path: &Path) -> PathBuf {
+    let mut path = spill_path.as_os_str().to_owned();
+    path.push(".offset");
+    PathBuf::from(path)
+}
+
+impl OfflineBuffer {
+    #[must_use]
+    pub fn new(
+        max_samples: usize,
+        max_memory: usize,
+        spill_path: Option<PathBuf>,
+        max_spill: u64,
+    ) -> Self {
+        // Samples spilled before a restart are still worth replaying
+        let spill_len = spill_path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).ok())
+            .map_or(0, |metadata| metadata.len());
+        let spill_offset = spill_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(offset_path(path)).ok())
+            .and_then(|offset| offset.trim().parse::<u64>().ok())
+            .filter(|offset| *offset <= spill_len)
+            .unwrap_or(0);
+        if spill_len > spill_offset {
+            info!(
+                "Found {} bytes of buffered realtime samples from a previous run",
+                spill_len - spill_offset
+            );
+        }
+        Self {
+            samples: VecDeque::new(),
+            memory: 0,
+            max_samples,
+            max_memory,
+            spill_path,
+            max_spill,
+            spill_len,
+            spill_offset,
+            writer: None,
+            reader: None,
+            next_spilled: None,
+            dropped: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn from_config(config: &UserConfig) -> Option<Self> {
+        config.offline_buffer.then(|| {
+            Self::new(
+                config.offline_buffer_max_samples as usize,
+                config.offline_buffer_max_memory as usize * 1024 * 1024,
+                config.offline_buffer_spill_path.as_ref().map(PathBuf::from),
+                config.offline_buffer_spill_max * 1024 * 1024,
+            )
+        })
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty() && self.spill_offset >= self.spill_len
+    }
+
+    pub fn push(&mut self, sample: String) {
+        self.memory += sample.len();
+        self.samples.push_back(sample);
+        while self.samples.len() > self.max_samples || self.memory > self.max_memory {
+            let Some(oldest) = self.samples.pop_front() else {
+                break;
+            };
+            self.memory -= oldest.len();
+            if !self.spill(&oldest) {
+                if self.dropped == 0 {
+                    warn!("Offline buffer is full, dropping the oldest realtime samples");
+                }
+                self.dropped += 1;
+            }
+        }
+    }
+
+    /// Append a sample to the spill file, returning whether it fit
+    fn spill(&mut self, sample: &str) -> bool {
+        let Some(path) = &self.spill_path else {
+            return false;
+        };
+        let len = sample.len() as u64 + 1;
+        if self.spill_len + len > self.max_spill {
+            return false;
+        }
+        let writer = match self.writer.take() {
+            Some(writer) => Ok(writer),
+            None => OpenOptions::new().create(true).append(true).open(path),
+        };
+        let written = writer.and_then(|mut writer| {
+            writeln!(writer, "{sample}")?;
+            self.writer = Some(writer);
+            Ok(())
+        });
+        if let Err(e) = written {
+            warn!("Failed to spill realtime sample to {}: {e}", path.display());
+            return false;
+        }
+        self.spill_len += len;
+        true
+    }
+
+    /// The next line of the spill file, including its newline
+    fn spilled_line(&mut self) -> Option<&str> {
+        if self.next_spilled.is_none() && self.spill_offset < self.spill_len {
+            let path = self.spill_path.clone()?;
+            match self.read_spilled_line(&path) {
+                Ok(line) if !line.is_empty() => self.next_spilled = Some(line),
+                Ok(_) => self.clear_spill(),
+                Err(e) => {
+                    warn!(
+                        "Failed to read spilled realtime samples from {}: {e}",
+                        path.display()
+                    );
+                    self.clear_spill();
+                }
+            }
+        }
+        self.next_spilled.as_deref()
+    }
+
+    fn read_spilled_line(&mut self, path: &Path) -> std::io::Result<String> {
+        let reader = if let Some(reader) = &mut self.reader {
+            reader
+        } else {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(self.spill_offset))?;
+            self.reader.insert(BufReader::new(file))
+        };
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        Ok(line)
+    }
+
+    /// The oldest sample, left in the buffer until `pop` confirms it was sent
+    pub fn peek(&mut self) -> Option<String> {
+        self.spilled_line()
+            .map(|line| line.trim_end().to_string())
+            .or_else(|| self.samples.front().cloned())
+    }
+
+    /// Drop the sample last returned by `peek`
+    pub fn pop(&mut self) {
+        if let Some(line) = self.spilled_line() {
+            self.spill_offset += line.len() as u64;
+            self.next_spilled = None;
+            if self.spill_offset >= self.spill_len {
+                self.clear_spill();
+            } else {
+                self.save_offset();
+            }
+        } else if let Some(sample) = self.samples.pop_front() {
+            self.memory -= sample.len();
+        }
+        if self.is_empty() && self.dropped > 0 {
+            warn!(
+                "Offline buffer replayed, {} samples were dropped while it was full",
+                self.dropped
+            );
+            self.dropped = 0;
+        }
+    }
+
+    fn save_offset(&self) {
+        let Some(path) = &self.spill_path else {
+            return;
+        };
+        let path = offset_path(path);
+        if let Err(e) = fs::write(&path, self.spill_offset.to_string()) {
+            debug!(
+                "Failed to save offline buffer offset to {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    fn clear_spill(&mut self) {
+        self.writer = None;
+        self.reader = None;
+        self.next_spilled = None;
+        if let Some(path) = &self.spill_path {
+            let _ = fs::remove_file(path);
+            let _ = fs::remove_file(offset_path(path));
+        }
+        self.spill_len = 0;
+        self.spill_offset = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(buffer: &mut OfflineBuffer, limit: usize) -> Vec<String> {
+        let mut samples = Vec::new();
+        while samples.len() < limit
+            && let Some(sample) = buffer.peek()
+        {
+            samples.push(sample);
+            buffer.pop();
+        }
+        samples
+    }
+
+    fn samples(range: std::ops::Range<u32>) -> Vec<String> {
+        range.map(|i| format!("{{\"sample\":{i}}}")).collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_samples_without_a_spill_file() {
+        let mut buffer = OfflineBuffer::new(3, usize::MAX, None, 0);
+        for sample in samples(0..5) {
+            buffer.push(sample);
+        }
+        assert_eq!(buffer.peek(), buffer.peek());
+        assert_eq!(drain(&mut buffer, usize::MAX), samples(2..5));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn replays_spilled_samples_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("offline.jsonl");
+        let mut buffer = OfflineBuffer::new(2, usize::MAX, Some(path.clone()), u64::MAX);
+        for sample in samples(0..4) {
+            buffer.push(sample);
+        }
+        assert_eq!(drain(&mut buffer, 1), samples(0..1));
+
+        // Spilling more while replay is under way keeps the order
+        for sample in samples(4..6) {
+            buffer.push(sample);
+        }
+        assert_eq!(drain(&mut buffer, usize::MAX), samples(1..6));
+        assert!(buffer.is_empty());
+        assert!(!path.exists());
+        assert!(!offset_path(&path).exists());
+    }
+
+    #[test]
+    fn stops_spilling_at_the_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("offline.jsonl");
+        let line = samples(0..1)[0].len() as u64 + 1;
+        let mut buffer = OfflineBuffer::new(1, usize::MAX, Some(path.clone()), line * 2);
+        for sample in samples(0..5) {
+            buffer.push(sample);
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len(), line * 2);
+        assert_eq!(
+            drain(&mut buffer, usize::MAX),
+            [&samples(0..2)[..], &samples(4..5)[..]].concat()
+        );
+    }
+
+    #[test]
+    fn resumes_replay_after_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("offline.jsonl");
+        let mut buffer = OfflineBuffer::new(1, usize::MAX, Some(path.clone()), u64::MAX);
+        for sample in samples(0..6) {
+            buffer.push(sample);
+        }
+        assert_eq!(drain(&mut buffer, 2), samples(0..2));
+        // Peeked but never confirmed, so it is sent again
+        assert_eq!(buffer.peek(), Some(samples(2..3).remove(0)));
+        drop(buffer);
+
+        let mut buffer = OfflineBuffer::new(1, usize::MAX, Some(path.clone()), u64::MAX);
+        assert_eq!(drain(&mut buffer, usize::MAX), samples(2..5));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn ignores_an_offset_past_the_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("offline.jsonl");
+        fs::write(&path, samples(0..2).join("\n") + "\n").unwrap();
+        fs::write(offset_path(&path), "4096").unwrap();
+        let mut buffer = OfflineBuffer::new(1, usize::MAX, Some(path), u64::MAX);
+        assert_eq!(drain(&mut buffer, usize::MAX), samples(0..2));
+    }
+}
@@ -1,16 +1,26 @@
 use crate::config::LogLevel;
+use crate::proxy::{connect_async as connect_async_via_proxy, proxy_for};
 use crate::rustls_config::create_dangerous_config;
 use log::Level;
+use std::collections::hash_map::RandomState;
 use std::fmt::Display;
-use std::sync::Arc;
-use std::time::Duration;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 use tokio_tungstenite::{
-    Connector, MaybeTlsStream, WebSocketStream, connect_async, connect_async_tls_with_config,
+    Connector, MaybeTlsStream, WebSocketStream, client_async_tls_with_config, connect_async,
+    connect_async_tls_with_config,
 };
 use url::{ParseError, Url};
 
+/// # Panics
+///
+/// When a logger has already been installed.
 pub fn init_logger(log_level: &LogLevel) {
     #[cfg(target_os = "windows")]
     simple_logger::set_up_windows_color_terminal();
@@ -24,6 +34,108 @@ pub fn init_logger(log_level: &LogLevel) {
     }
 }
 
+/// `command` run through `sh -c`, or `cmd /C` on Windows
+#[must_use]
+pub fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    }
+    #[cfg(not(windows))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+/// Run `cmd` with stdout and stderr captured, killing it once `timeout` has passed.
+///
+/// On Unix the command runs in its own process group and the whole group is
+/// killed, so pipelines and background jobs do not outlive it. A background job
+/// that still holds stdout or stderr open after the command exits counts as
+/// part of the command and is killed at the same deadline.
+///
+/// # Errors
+///
+/// When the command cannot be started or waited for, or runs past `timeout`.
+pub fn output_with_timeout(mut cmd: Command, timeout: Duration) -> Result<Output, String> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to start: {e}"))?;
+    let deadline = Instant::now() + timeout;
+    let timed_out = || format!("timed out after {}s", timeout.as_secs());
+
+    // Drain both pipes on their own threads so a chatty command cannot block on a full pipe
+    let (tx, rx) = mpsc::channel();
+    let pipes: [Option<Box<dyn Read + Send>>; 2] = [
+        child.stdout.take().map(|pipe| Box::new(pipe) as _),
+        child.stderr.take().map(|pipe| Box::new(pipe) as _),
+    ];
+    for (index, pipe) in pipes.into_iter().enumerate() {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut output);
+            }
+            let _ = tx.send((index, output));
+        });
+    }
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(50)),
+            Ok(None) => {
+                kill_process_group(&mut child);
+                return Err(timed_out());
+            }
+            Err(e) => {
+                kill_process_group(&mut child);
+                return Err(format!("failed to wait: {e}"));
+            }
+        }
+    };
+
+    let mut output = [Vec::new(), Vec::new()];
+    for _ in 0..output.len() {
+        let Ok((index, bytes)) =
+            rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        else {
+            kill_process_group(&mut child);
+            return Err(timed_out());
+        };
+        output[index] = bytes;
+    }
+    let [stdout, stderr] = output;
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Kill `child` and, on Unix, every process left in its process group
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Ok(pgid) = libc::pid_t::try_from(child.id()) {
+        // The group id stays reserved while any member is alive, even after the leader was reaped
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
 #[derive(Debug, Clone)]
 pub struct ConnectionUrls {
     pub basic_info: String,
@@ -42,6 +154,13 @@ impl Display for ConnectionUrls {
     }
 }
 
+/// # Errors
+///
+/// When `http_server` or `ws_server` is not a valid URL.
+///
+/// # Panics
+///
+/// Never in practice: only http and https URLs are turned into ws and wss.
 pub fn build_urls(
     http_server: &str,
     ws_server: Option<&String>,
@@ -81,6 +200,71 @@ pub fn build_urls(
     Ok(connection_urls)
 }
 
+/// A non-cryptographic random number, good enough for jitter and STUN
+/// transaction ids without pulling in an RNG crate
+#[must_use]
+pub fn random_u64() -> u64 {
+    // RandomState is seeded randomly for every instance
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    hasher.finish()
+}
+
+/// `min`, `2 * min`, `4 * min`, ... capped at `max`
+#[must_use]
+pub fn backoff_delay(attempt: u32, min: Duration, max: Duration) -> Duration {
+    min.saturating_mul(2u32.saturating_pow(attempt)).min(max)
+}
+
+/// Capped exponential backoff with full jitter for reconnecting to the server.
+/// Every delay is drawn uniformly from zero up to `min * 2^attempt` (at most `max`),
+/// so agents dropped by the same server restart do not come back in lockstep.
+pub struct ReconnectBackoff {
+    min: Duration,
+    max: Duration,
+    /// A connection that stayed up this long starts the next backoff from scratch
+    reset_after: Duration,
+    attempt: u32,
+    connected_at: Option<Instant>,
+}
+
+impl ReconnectBackoff {
+    #[must_use]
+    pub fn new(min: Duration, max: Duration, reset_after: Duration) -> Self {
+        Self {
+            min,
+            max,
+            reset_after,
+            attempt: 0,
+            connected_at: None,
+        }
+    }
+
+    pub fn connected(&mut self) {
+        self.connected_at = Some(Instant::now());
+    }
+
+    /// Attempt number and delay before the next connection attempt, after a
+    /// failed attempt or a dropped connection
+    pub fn next_delay(&mut self) -> (u32, Duration) {
+        if self
+            .connected_at
+            .take()
+            .is_some_and(|connected_at| connected_at.elapsed() >= self.reset_after)
+        {
+            self.attempt = 0;
+        }
+        let cap = backoff_delay(self.attempt, self.min, self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let fraction = random_u64() as f64 / u64::MAX as f64;
+        (self.attempt, cap.mul_f64(fraction))
+    }
+}
+
+/// # Errors
+///
+/// When the URL is invalid, the connection times out or the handshake fails.
 pub async fn connect_ws(
     url: &str,
     tls: bool,
@@ -88,6 +272,26 @@ pub async fn connect_ws(
 ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, String> {
     let connection_timeout = Duration::from_secs(10);
 
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid WebSocket URL: {e}"))?;
+    if let Some(host) = parsed.host_str()
+        && let Some(proxy) = proxy_for(host)
+    {
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        let connector =
+            (tls && skip_verify).then(|| Connector::Rustls(Arc::new(create_dangerous_config())));
+        return timeout(connection_timeout, async {
+            let stream = connect_async_via_proxy(proxy, host, port, connection_timeout)
+                .await
+                .map_err(|e| format!("Failed to connect through proxy: {e}"))?;
+            client_async_tls_with_config(url, stream, None, connector)
+                .await
+                .map(|ws| ws.0)
+                .map_err(|_| "Failed to establish WebSocket connection".to_string())
+        })
+        .await
+        .map_err(|_| "WebSocket connection timeout".to_string())?;
+    }
+
     if tls {
         if skip_verify {
             timeout(
@@ -122,17 +326,30 @@ pub async fn connect_ws(
     }
 }
 
+/// An agent for requests to `url`, going through the configured proxy unless
+/// `no_proxy` covers the host
 #[cfg(feature = "ureq-support")]
-pub fn create_ureq_agent(disable_verification: bool) -> ureq::Agent {
-    let config = ureq::Agent::config_builder()
+pub fn create_ureq_agent(url: &str, disable_verification: bool) -> ureq::Agent {
+    let mut config = ureq::Agent::config_builder()
         .tls_config(
             ureq::tls::TlsConfig::builder()
                 .disable_verification(disable_verification)
                 .build(),
         )
-        .timeout_global(Some(Duration::from_secs(5)))
-        .build();
-    config.new_agent()
+        .timeout_global(Some(Duration::from_secs(5)));
+    // The configured proxy replaces ureq's own proxy from the environment.
+    // Bypassing is decided here since ureq's NO_PROXY matching has no CIDR ranges.
+    if crate::proxy::outbound_proxy().is_some() {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        let proxy = host
+            .as_deref()
+            .and_then(proxy_for)
+            .and_then(crate::proxy::OutboundProxy::ureq_proxy);
+        config = config.proxy(proxy);
+    }
+    config.build().new_agent()
 }
 
 #[cfg(feature = "nyquest-support")]
@@ -146,3 +363,52 @@ pub fn create_nyquest_client(disable_verification: bool) -> nyquest::BlockingCli
     }
     client.build_blocking().unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delay_doubles_up_to_the_cap() {
+        let (min, max) = (Duration::from_secs(10), Duration::from_mins(10));
+        let delays: Vec<u64> = (0..8)
+            .map(|attempt| backoff_delay(attempt, min, max).as_secs())
+            .collect();
+        assert_eq!(delays, [10, 20, 40, 80, 160, 320, 600, 600]);
+        assert_eq!(backoff_delay(u32::MAX, min, max), max);
+    }
+
+    #[test]
+    fn reconnect_delays_stay_below_the_cap() {
+        let (min, max) = (Duration::from_secs(1), Duration::from_secs(8));
+        let mut backoff = ReconnectBackoff::new(min, max, Duration::from_mins(1));
+        for expected_attempt in 1..=10 {
+            let (attempt, delay) = backoff.next_delay();
+            assert_eq!(attempt, expected_attempt);
+            assert!(delay <= backoff_delay(attempt - 1, min, max));
+        }
+    }
+
+    #[test]
+    fn reconnect_backoff_resets_after_a_stable_connection() {
+        let (min, max) = (Duration::from_secs(1), Duration::from_secs(8));
+
+        let mut backoff = ReconnectBackoff::new(min, max, Duration::ZERO);
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.connected();
+        assert_eq!(backoff.next_delay().0, 1);
+
+        // A connection that drops before the reset time keeps counting up
+        let mut backoff = ReconnectBackoff::new(min, max, Duration::from_mins(1));
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.connected();
+        assert_eq!(backoff.next_delay().0, 3);
+    }
+
+    #[test]
+    fn random_numbers_differ() {
+        assert_ne!(random_u64(), random_u64());
+    }
+}
@@ -0,0 +1,635 @@
+use crate::config::UserConfig;
+use crate::get_info::Collector;
+use crate::get_info::processes::{pids, read_cmdline};
+use crate::utils::{backoff_delay, output_with_timeout, shell_command};
+use log::{debug, error, info, trace, warn};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const PROC_ROOT: &str = "/proc";
+/// Seconds, same as the config defaults
+const DEFAULT_BACKOFF_MIN: u64 = 10;
+const DEFAULT_BACKOFF_MAX: u64 = 600;
+const DEFAULT_RESTART_TIMEOUT: u64 = 60;
+/// systemctl blocks for as long as systemd or D-Bus is stuck
+const SYSTEMCTL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something that must be running
+#[derive(Debug, Clone)]
+pub enum WatchTarget {
+    /// Pattern matched against the whole process name (`/proc/[pid]/comm`)
+    ProcessName { pattern: String, regex: Regex },
+    /// Pattern searched for anywhere in the command line
+    ProcessCmdline { pattern: String, regex: Regex },
+    /// File holding the pid of a running process
+    Pidfile(PathBuf),
+    /// systemd unit whose `ActiveState` must be `active`
+    Unit(String),
+}
+
+impl WatchTarget {
+    /// # Errors
+    ///
+    /// When `pattern` is not a valid regular expression.
+    pub fn process_name(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(WatchTarget::ProcessName {
+            pattern: pattern.to_string(),
+            regex: Regex::new(&format!("^(?:{pattern})$"))?,
+        })
+    }
+
+    /// # Errors
+    ///
+    /// When `pattern` is not a valid regular expression.
+    pub fn process_cmdline(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(WatchTarget::ProcessCmdline {
+            pattern: pattern.to_string(),
+            regex: Regex::new(pattern)?,
+        })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            WatchTarget::ProcessName { .. } => "process",
+            WatchTarget::ProcessCmdline { .. } => "cmdline",
+            WatchTarget::Pidfile(_) => "pidfile",
+            WatchTarget::Unit(_) => "unit",
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            WatchTarget::ProcessName { pattern, .. }
+            | WatchTarget::ProcessCmdline { pattern, .. } => pattern.clone(),
+            WatchTarget::Pidfile(path) => path.display().to_string(),
+            WatchTarget::Unit(unit) => unit.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TargetState {
+    missing: bool,
+    restart_attempts: u32,
+    next_restart: Option<Instant>,
+    /// The last restart hook started for this target
+    hook: Option<JoinHandle<()>>,
+}
+
+impl TargetState {
+    fn hook_running(&self) -> bool {
+        self.hook.as_ref().is_some_and(|hook| !hook.is_finished())
+    }
+}
+
+/// Parse `systemctl show --property=... unit...` output: one block of
+/// `Key=Value` lines per unit, separated by blank lines, in argument order
+#[must_use]
+pub fn parse_systemctl_show(output: &str) -> Vec<HashMap<String, String>> {
+    let mut units = Vec::new();
+    let mut current = HashMap::new();
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                units.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            current.insert(key.to_string(), value.to_string());
+        }
+    }
+    if !current.is_empty() {
+        units.push(current);
+    }
+    units
+}
+
+/// Why a unit from `systemctl show` is not running, or `None` when it is active
+fn unit_problem(properties: &HashMap<String, String>) -> Option<String> {
+    let get = |key: &str| properties.get(key).map_or("unknown", String::as_str);
+    if get("LoadState") == "not-found" {
+        Some("not found".to_string())
+    } else if get("ActiveState") == "active" {
+        None
+    } else {
+        Some(format!("{}/{}", get("ActiveState"), get("SubState")))
+    }
+}
+
+/// Why each unit is not running, or `None` when it is active, from one
+/// `<systemctl> show` run that is killed after `timeout`
+///
+/// # Errors
+///
+/// When systemctl cannot be run, times out or exits unsuccessfully.
+pub fn query_units(
+    systemctl: &str,
+    units: &[String],
+    timeout: Duration,
+) -> Result<Vec<Option<String>>, String> {
+    let mut cmd = Command::new(systemctl);
+    cmd.arg("show")
+        .arg("--property=LoadState,ActiveState,SubState")
+        .args(units);
+    let output = output_with_timeout(cmd, timeout).map_err(|e| format!("{systemctl} show {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{systemctl} show failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let blocks = parse_systemctl_show(&String::from_utf8_lossy(&output.stdout));
+    Ok((0..units.len())
+        .map(|i| unit_problem(blocks.get(i)?))
+        .collect())
+}
+
+pub struct Watchdog {
+    targets: Vec<(WatchTarget, TargetState)>,
+    restart_command: Option<String>,
+    restart_timeout: Duration,
+    backoff_min: Duration,
+    backoff_max: Duration,
+    proc_root: PathBuf,
+    systemctl: String,
+    /// `systemctl show` running in the background, so a stuck systemd never
+    /// holds up the realtime loop
+    unit_check: Option<JoinHandle<Result<Vec<Option<String>>, String>>>,
+    /// Result of the last finished unit check
+    unit_states: Vec<Option<String>>,
+    /// Warn about a broken systemctl once, not on every tick
+    systemctl_failing: bool,
+}
+
+impl Watchdog {
+    #[must_use]
+    pub fn new(targets: Vec<WatchTarget>) -> Self {
+        Self {
+            targets: targets
+                .into_iter()
+                .map(|target| (target, TargetState::default()))
+                .collect(),
+            restart_command: None,
+            restart_timeout: Duration::from_secs(DEFAULT_RESTART_TIMEOUT),
+            backoff_min: Duration::from_secs(DEFAULT_BACKOFF_MIN),
+            backoff_max: Duration::from_secs(DEFAULT_BACKOFF_MAX),
+            proc_root: PathBuf::from(PROC_ROOT),
+            systemctl: "systemctl".to_string(),
+            unit_check: None,
+            unit_states: Vec::new(),
+            systemctl_failing: false,
+        }
+    }
+
+    /// `None` when nothing is configured to be watched
+    pub fn from_config(config: &UserConfig) -> Option<Self> {
+        // Regexes were validated when the config was decoded
+        let targets: Vec<WatchTarget> = config
+            .watchdog_process_names
+            .iter()
+            .filter_map(|pattern| WatchTarget::process_name(pattern).ok())
+            .chain(
+                config
+                    .watchdog_process_cmdlines
+                    .iter()
+                    .filter_map(|pattern| WatchTarget::process_cmdline(pattern).ok()),
+            )
+            .chain(
+                config
+                    .watchdog_pidfiles
+                    .iter()
+                    .map(|path| WatchTarget::Pidfile(PathBuf::from(path))),
+            )
+            .chain(config.watchdog_units.iter().cloned().map(WatchTarget::Unit))
+            .collect();
+        if targets.is_empty() {
+            return None;
+        }
+
+        Some(
+            Self::new(targets)
+                .with_restart_command(config.watchdog_restart_command.clone())
+                .with_restart_timeout(Duration::from_secs(config.watchdog_restart_timeout))
+                .with_backoff(
+                    Duration::from_secs(config.watchdog_restart_backoff_min),
+                    Duration::from_secs(config.watchdog_restart_backoff_max),
+                ),
+        )
+    }
+
+    #[must_use]
+    pub fn with_restart_command(mut self, restart_command: Option<String>) -> Self {
+        self.restart_command = restart_command;
+        self
+    }
+
+    /// The hook is killed, with anything it left running in its process group,
+    /// once `restart_timeout` has passed
+    #[must_use]
+    pub fn with_restart_timeout(mut self, restart_timeout: Duration) -> Self {
+        self.restart_timeout = restart_timeout;
+        self
+    }
+
+    #[must_use]
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.backoff_min = min;
+        self.backoff_max = max;
+        self
+    }
+
+    #[must_use]
+    pub fn with_proc_root(mut self, proc_root: PathBuf) -> Self {
+        self.proc_root = proc_root;
+        self
+    }
+
+    /// Program called as `<systemctl> show ...`; point it at a stub to test without systemd
+    #[must_use]
+    pub fn with_systemctl(mut self, systemctl: &str) -> Self {
+        self.systemctl = systemctl.to_string();
+        self
+    }
+
+    /// (comm, cmdline) of every process; cmdlines are only read when needed
+    fn processes(&self) -> Vec<(String, String)> {
+        let need_cmdline = self
+            .targets
+            .iter()
+            .any(|(target, _)| matches!(target, WatchTarget::ProcessCmdline { .. }));
+
+        pids(&self.proc_root)
+            .into_iter()
+            .filter_map(|pid| {
+                let comm = fs::read_to_string(self.proc_root.join(pid.to_string()).join("comm"))
+                    .ok()?
+                    .trim_end()
+                    .to_string();
+                let cmdline = if need_cmdline {
+                    read_cmdline(&self.proc_root, pid).unwrap_or_default()
+                } else {
+                    String::new()
+                };
+                Some((comm, cmdline))
+            })
+            .collect()
+    }
+
+    fn pidfile_alive(&self, path: &Path) -> bool {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+            .is_some_and(|pid| self.proc_root.join(pid.to_string()).is_dir())
+    }
+
+    /// Why each unit is not running as of the last finished `systemctl show`,
+    /// starting the next one in the background. Units count as running until
+    /// the first check finishes or while systemctl is failing.
+    fn unit_states(&mut self, units: &[String]) -> Vec<Option<String>> {
+        if units.is_empty() {
+            return Vec::new();
+        }
+
+        if self
+            .unit_check
+            .as_ref()
+            .is_some_and(JoinHandle::is_finished)
+            && let Some(Ok(result)) = self.unit_check.take().map(JoinHandle::join)
+        {
+            match result {
+                Ok(states) => {
+                    self.systemctl_failing = false;
+                    self.unit_states = states;
+                }
+                Err(error) => {
+                    if !self.systemctl_failing {
+                        warn!("Watchdog: {error}");
+                        self.systemctl_failing = true;
+                    }
+                    self.unit_states.clear();
+                }
+            }
+        }
+        if self.unit_check.is_none() {
+            let (systemctl, units) = (self.systemctl.clone(), units.to_vec());
+            self.unit_check = Some(thread::spawn(move || {
+                query_units(&systemctl, &units, SYSTEMCTL_TIMEOUT)
+            }));
+        }
+
+        (0..units.len())
+            .map(|i| self.unit_states.get(i).cloned().flatten())
+            .collect()
+    }
+
+    /// Run the restart hook on its own thread so the realtime loop is not held
+    /// up while the service restarts
+    fn run_restart_hook(&self, target: &WatchTarget) -> Option<JoinHandle<()>> {
+        let command = self.restart_command.as_ref()?;
+        let (kind, name) = (target.kind(), target.name());
+
+        let mut cmd = shell_command(command);
+        cmd.env("KOMARI_WATCHDOG_KIND", kind)
+            .env("KOMARI_WATCHDOG_TARGET", &name);
+        let timeout = self.restart_timeout;
+
+        Some(thread::spawn(move || {
+            match output_with_timeout(cmd, timeout) {
+                Ok(output) if output.status.success() => {
+                    info!("Watchdog: restart hook for {kind} {name} finished");
+                }
+                Ok(output) => error!(
+                    "Watchdog: restart hook for {kind} {name} exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => error!("Watchdog: restart hook for {kind} {name} {e}"),
+            }
+        }))
+    }
+
+    /// Descriptions of the targets that are not running
+    pub fn check(&mut self) -> Vec<String> {
+        let processes = if self.targets.iter().any(|(target, _)| {
+            matches!(
+                target,
+                WatchTarget::ProcessName { .. } | WatchTarget::ProcessCmdline { .. }
+            )
+        }) {
+            self.processes()
+        } else {
+            Vec::new()
+        };
+
+        let units: Vec<String> = self
+            .targets
+            .iter()
+            .filter_map(|(target, _)| match target {
+                WatchTarget::Unit(unit) => Some(unit.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut unit_states = self.unit_states(&units).into_iter();
+
+        let problems: Vec<Option<String>> = self
+            .targets
+            .iter()
+            .map(|(target, _)| match target {
+                WatchTarget::ProcessName { regex, .. } => {
+                    (!processes.iter().any(|(comm, _)| regex.is_match(comm)))
+                        .then(|| "not running".to_string())
+                }
+                WatchTarget::ProcessCmdline { regex, .. } => {
+                    (!processes.iter().any(|(_, cmdline)| regex.is_match(cmdline)))
+                        .then(|| "not running".to_string())
+                }
+                WatchTarget::Pidfile(path) => {
+                    (!self.pidfile_alive(path)).then(|| "not running".to_string())
+                }
+                WatchTarget::Unit(_) => unit_states.next().flatten(),
+            })
+            .collect();
+
+        let now = Instant::now();
+        let mut missing = Vec::new();
+        for (i, problem) in problems.into_iter().enumerate() {
+            let (target, state) = &self.targets[i];
+            let description = format!("{} {}", target.kind(), target.name());
+
+            let Some(problem) = problem else {
+                if state.missing {
+                    info!("Watchdog: {description} is running again");
+                }
+                // A hook that is still running must not overlap with the next one
+                let hook = self.targets[i].1.hook.take();
+                self.targets[i].1 = TargetState {
+                    hook,
+                    ..TargetState::default()
+                };
+                continue;
+            };
+
+            if !state.missing {
+                warn!("Watchdog: {description} is {problem}");
+            }
+            missing.push(format!("{description} ({problem})"));
+
+            let due = state.next_restart.is_none_or(|next| now >= next);
+            if self.restart_command.is_some() && due && state.hook_running() {
+                debug!("Watchdog: restart hook for {description} is still running");
+            } else if self.restart_command.is_some() && due {
+                let delay =
+                    backoff_delay(state.restart_attempts, self.backoff_min, self.backoff_max);
+                info!(
+                    "Watchdog: running restart hook for {description} (attempt {}), next attempt in {delay:?}",
+                    state.restart_attempts + 1
+                );
+                let hook = self.run_restart_hook(target);
+                let state = &mut self.targets[i].1;
+                state.hook = hook;
+                state.restart_attempts += 1;
+                state.next_restart = Some(now + delay);
+            }
+            self.targets[i].1.missing = true;
+        }
+
+        trace!("WATCHDOG successfully checked, missing: {missing:?}");
+        missing
+    }
+}
+
+impl Collector for Watchdog {
+    /// Message for `RealTimeInfo.message`, empty when everything is running
+    type Output = String;
+
+    fn collect(&mut self) -> String {
+        let missing = self.check();
+        if missing.is_empty() {
+            String::new()
+        } else {
+            format!("Watchdog: missing {}", missing.join(", "))
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn stub(dir: &Path, name: &str, script: &str) -> String {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path.display().to_string()
+    }
+
+    fn units(names: &[&str]) -> Vec<String> {
+        names.iter().map(ToString::to_string).collect()
+    }
+
+    const SHOW_OUTPUT: &str = "LoadState=loaded\nActiveState=active\nSubState=running\n\nLoadState=not-found\nActiveState=inactive\nSubState=dead\n\nLoadState=loaded\nActiveState=failed\nSubState=failed\n";
+
+    #[test]
+    fn parse_systemctl_show_splits_blocks_in_order() {
+        let blocks = parse_systemctl_show(SHOW_OUTPUT);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0]["SubState"], "running");
+        assert_eq!(blocks[1]["LoadState"], "not-found");
+        assert_eq!(blocks[2]["ActiveState"], "failed");
+        assert!(parse_systemctl_show("\n\n").is_empty());
+    }
+
+    #[test]
+    fn unit_problem_describes_inactive_units() {
+        let blocks = parse_systemctl_show(SHOW_OUTPUT);
+        assert_eq!(unit_problem(&blocks[0]), None);
+        assert_eq!(unit_problem(&blocks[1]).as_deref(), Some("not found"));
+        assert_eq!(unit_problem(&blocks[2]).as_deref(), Some("failed/failed"));
+        assert_eq!(
+            unit_problem(&HashMap::new()).as_deref(),
+            Some("unknown/unknown")
+        );
+    }
+
+    #[test]
+    fn query_units_reads_a_stubbed_systemctl() {
+        let dir = tempfile::tempdir().unwrap();
+        let systemctl = stub(
+            dir.path(),
+            "systemctl",
+            &format!("printf '{}'", SHOW_OUTPUT.replace('\n', "\\n")),
+        );
+        let states = query_units(
+            &systemctl,
+            &units(&["sshd.service", "gone.service", "mysql.service"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(
+            states,
+            [
+                None,
+                Some("not found".to_string()),
+                Some("failed/failed".to_string())
+            ]
+        );
+
+        let failing = stub(
+            dir.path(),
+            "failing",
+            "echo 'Failed to connect to bus' >&2; exit 1",
+        );
+        assert_eq!(
+            query_units(&failing, &units(&["a.service"]), Duration::from_secs(5)).unwrap_err(),
+            format!("{failing} show failed: Failed to connect to bus")
+        );
+
+        let hanging = stub(dir.path(), "hanging", "sleep 30");
+        let started = Instant::now();
+        assert!(query_units(&hanging, &units(&["a.service"]), Duration::from_millis(300)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stuck_systemctl_does_not_block_the_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let systemctl = stub(dir.path(), "systemctl", "sleep 30");
+        let mut watchdog = Watchdog::new(vec![WatchTarget::Unit("sshd.service".to_string())])
+            .with_systemctl(&systemctl);
+
+        let started = Instant::now();
+        assert!(watchdog.check().is_empty());
+        assert!(watchdog.check().is_empty());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unit_states_come_from_the_last_finished_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let systemctl = stub(
+            dir.path(),
+            "systemctl",
+            "printf 'LoadState=loaded\\nActiveState=failed\\nSubState=failed\\n'",
+        );
+        let mut watchdog = Watchdog::new(vec![WatchTarget::Unit("app.service".to_string())])
+            .with_systemctl(&systemctl);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let missing = loop {
+            let missing = watchdog.check();
+            if !missing.is_empty() || Instant::now() > deadline {
+                break missing;
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        assert_eq!(missing, ["unit app.service (failed/failed)"]);
+    }
+
+    #[test]
+    fn processes_and_pidfiles_are_read_from_proc_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let proc_root = dir.path().join("proc");
+        for (pid, comm, cmdline) in [
+            (1, "systemd", "/sbin/init\0splash\0"),
+            (412, "java", "java\0-jar\0/srv/app.jar\0"),
+        ] {
+            let pid_dir = proc_root.join(pid.to_string());
+            fs::create_dir_all(&pid_dir).unwrap();
+            fs::write(pid_dir.join("comm"), format!("{comm}\n")).unwrap();
+            fs::write(pid_dir.join("cmdline"), cmdline).unwrap();
+        }
+        let alive = dir.path().join("app.pid");
+        let stale = dir.path().join("old.pid");
+        fs::write(&alive, "412\n").unwrap();
+        fs::write(&stale, "9999\n").unwrap();
+
+        let mut watchdog = Watchdog::new(vec![
+            WatchTarget::process_name("java").unwrap(),
+            WatchTarget::process_name("nginx").unwrap(),
+            WatchTarget::process_cmdline(r"-jar .*app\.jar").unwrap(),
+            WatchTarget::Pidfile(alive),
+            WatchTarget::Pidfile(stale.clone()),
+        ])
+        .with_proc_root(proc_root);
+        assert_eq!(
+            watchdog.check(),
+            [
+                "process nginx (not running)".to_string(),
+                format!("pidfile {} (not running)", stale.display())
+            ]
+        );
+    }
+
+    #[test]
+    fn restart_hook_does_not_overlap_with_itself() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("hook.log");
+        let mut watchdog = Watchdog::new(vec![WatchTarget::process_name("nginx").unwrap()])
+            .with_proc_root(dir.path().join("proc"))
+            .with_restart_command(Some(format!(
+                "echo \"$KOMARI_WATCHDOG_TARGET\" >> {}; sleep 30",
+                log.display()
+            )))
+            .with_restart_timeout(Duration::from_secs(2))
+            .with_backoff(Duration::ZERO, Duration::ZERO);
+
+        for _ in 0..5 {
+            watchdog.check();
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(fs::read_to_string(&log).unwrap(), "nginx\n");
+
+        // The timed out hook is killed, after which the next one may start
+        thread::sleep(Duration::from_secs(3));
+        watchdog.check();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(fs::read_to_string(&log).unwrap(), "nginx\nnginx\n");
+    }
+}